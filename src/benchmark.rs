@@ -0,0 +1,213 @@
+use std::{
+    fs::File,
+    io,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use log::warn;
+use serde::Serialize;
+
+use crate::{vulkan::device_info::DeviceInfo, UserSettings};
+
+/// How often the benchmark frame rate is printed.
+const REPORT_PERIOD: Duration = Duration::from_secs(5);
+
+/// Bumped whenever the layout of `BenchmarkReport` changes.
+const SCHEMA_VERSION: u32 = 2;
+
+/// Tracks the benchmark state across frames, mirroring the original `CheckAndUpdateBenchmarkState`.
+pub struct Benchmark {
+    scene_index: usize,
+    scene_start: Instant,
+    /// When the warmup of the current scene ended, if it has.
+    measure_start: Option<Instant>,
+    warmup_frames: usize,
+    period_start: Instant,
+    period_frames: u32,
+    frame_times: Vec<Duration>,
+    total_samples: u64,
+    scenes: Vec<SceneReport>,
+}
+
+#[derive(Serialize)]
+pub struct BenchmarkReport<'a> {
+    pub schema_version: u32,
+    pub device: DeviceInfo,
+    pub resolution: [u32; 2],
+    pub settings: BenchmarkSettings,
+    pub scenes: &'a [SceneReport],
+}
+
+#[derive(Serialize)]
+pub struct BenchmarkSettings {
+    pub samples: u32,
+    pub bounces: u32,
+    pub max_samples: u32,
+    pub max_time: u32,
+    pub warmup: u32,
+}
+
+/// Frame time statistics (in milliseconds) of one benchmarked scene.
+#[derive(Serialize)]
+pub struct SceneReport {
+    pub scene_index: usize,
+    /// Frames rendered before measuring started, which the statistics leave out.
+    pub warmup_frames: usize,
+    pub frames: usize,
+    pub mean: f64,
+    pub median: f64,
+    pub p1: f64,
+    pub p99: f64,
+    pub samples_per_second: f64,
+}
+
+impl Benchmark {
+    pub fn new(scene_index: usize) -> Benchmark {
+        let now = Instant::now();
+        println!("\nBenchmark: Start scene #{}", scene_index);
+
+        Benchmark {
+            scene_index,
+            scene_start: now,
+            measure_start: None,
+            warmup_frames: 0,
+            period_start: now,
+            period_frames: 0,
+            frame_times: Vec::new(),
+            total_samples: 0,
+            scenes: Vec::new(),
+        }
+    }
+
+    /// Records a frame that took `frame_time` and traced `samples` samples per pixel. Returns
+    /// whether the benchmark is over, i.e. the scene reached its time or sample limit.
+    pub fn update(
+        &mut self,
+        now: Instant,
+        frame_time: Duration,
+        samples: u32,
+        settings: &UserSettings,
+    ) -> bool {
+        // the first frame of a scene includes its setup, so it's always part of the warmup.
+        match self.measure_start {
+            Some(_) => {
+                self.frame_times.push(frame_time);
+                self.total_samples += samples as u64;
+            }
+            None => {
+                self.warmup_frames += 1;
+                let warmup = Duration::from_secs(settings.benchmark_warmup as u64);
+                if now - self.scene_start >= warmup {
+                    self.measure_start = Some(now);
+                }
+            }
+        }
+
+        // Print out the frame rate at regular intervals.
+        let period = now - self.period_start;
+        if self.period_frames != 0 && period >= REPORT_PERIOD {
+            println!(
+                "Benchmark: {:.1} fps",
+                self.period_frames as f64 / period.as_secs_f64()
+            );
+            self.period_start = now;
+            self.period_frames = 0;
+        }
+        self.period_frames += 1;
+
+        // Bail out from the scene if we've reached the time or sample limit.
+        let time_limit_reached =
+            now - self.scene_start > Duration::from_secs(settings.benchmark_max_time as u64);
+        let sample_limit_reached = samples == 0;
+        if !time_limit_reached && !sample_limit_reached {
+            return false;
+        }
+
+        self.finish_scene(now);
+
+        // TODO: move on to the next scene when benchmark_next_scenes is set, once scenes are
+        // ported.
+        true
+    }
+
+    /// Adds the statistics of the current scene to the report, unless it was already finished.
+    pub fn finish_scene(&mut self, now: Instant) {
+        if self.frame_times.is_empty() {
+            // e.g. the sample limit was reached while warming up.
+            if self.warmup_frames != 0 {
+                warn!(
+                    "Benchmark: Scene #{} ended during its warmup ({} frames); nothing was \
+                     measured",
+                    self.scene_index, self.warmup_frames
+                );
+                self.warmup_frames = 0;
+            }
+            return;
+        }
+
+        let mut frame_times: Vec<f64> = self
+            .frame_times
+            .drain(..)
+            .map(|t| t.as_secs_f64() * 1000.0)
+            .collect();
+        frame_times.sort_by(f64::total_cmp);
+
+        let percentile =
+            |p: f64| frame_times[((frame_times.len() - 1) as f64 * p).round() as usize];
+
+        let measure_start = self.measure_start.unwrap_or(self.scene_start);
+        let report = SceneReport {
+            scene_index: self.scene_index,
+            warmup_frames: self.warmup_frames,
+            frames: frame_times.len(),
+            mean: frame_times.iter().sum::<f64>() / frame_times.len() as f64,
+            median: percentile(0.5),
+            p1: percentile(0.01),
+            p99: percentile(0.99),
+            samples_per_second: self.total_samples as f64
+                / (now - measure_start).as_secs_f64().max(f64::EPSILON),
+        };
+        println!(
+            "Benchmark: Scene #{} ({} warmup + {} measured frames) mean {:.2} ms, median {:.2} ms, \
+             p1 {:.2} ms, p99 {:.2} ms",
+            report.scene_index,
+            report.warmup_frames,
+            report.frames,
+            report.mean,
+            report.median,
+            report.p1,
+            report.p99
+        );
+
+        self.scenes.push(report);
+        self.total_samples = 0;
+        self.measure_start = None;
+        self.warmup_frames = 0;
+    }
+
+    /// Writes the statistics of every finished scene to `path` as JSON.
+    pub fn write_report(
+        &self,
+        path: &Path,
+        device: DeviceInfo,
+        resolution: [u32; 2],
+        settings: &UserSettings,
+    ) -> io::Result<()> {
+        let report = BenchmarkReport {
+            schema_version: SCHEMA_VERSION,
+            device,
+            resolution,
+            settings: BenchmarkSettings {
+                samples: settings.number_of_samples,
+                bounces: settings.number_of_bounces,
+                max_samples: settings.max_number_of_samples,
+                max_time: settings.benchmark_max_time,
+                warmup: settings.benchmark_warmup,
+            },
+            scenes: &self.scenes,
+        };
+        serde_json::to_writer_pretty(File::create(path)?, &report)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,167 @@
+use std::{collections::HashMap, fs, io, path::Path};
+
+use log::warn;
+use serde::{
+    de::{value::StrDeserializer, IntoDeserializer},
+    Deserialize,
+};
+use winit::event::VirtualKeyCode;
+
+/// Something a key press does, named in the `[keymap]` section of the config file.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Quit,
+    ToggleSettings,
+    ToggleOverlay,
+    ToggleHeatmap,
+    ToggleFreezeSample,
+    ToggleLens,
+    ResetSettings,
+    SamplesDown,
+    SamplesUp,
+    BouncesDown,
+    BouncesUp,
+}
+
+const DEFAULT_BINDINGS: [(Action, VirtualKeyCode); 11] = [
+    (Action::Quit, VirtualKeyCode::Escape),
+    (Action::ToggleSettings, VirtualKeyCode::F1),
+    (Action::ToggleOverlay, VirtualKeyCode::F2),
+    (Action::ToggleHeatmap, VirtualKeyCode::H),
+    (Action::ToggleFreezeSample, VirtualKeyCode::N),
+    (Action::ToggleLens, VirtualKeyCode::L),
+    (Action::ResetSettings, VirtualKeyCode::Back),
+    (Action::SamplesDown, VirtualKeyCode::LBracket),
+    (Action::SamplesUp, VirtualKeyCode::RBracket),
+    (Action::BouncesDown, VirtualKeyCode::Minus),
+    (Action::BouncesUp, VirtualKeyCode::Equals),
+];
+
+/// The config file; only the keymap is configurable so far.
+#[derive(Deserialize)]
+struct Config {
+    #[serde(default)]
+    keymap: HashMap<String, String>,
+}
+
+/// Which key triggers which action. Bindings are kept in `DEFAULT_BINDINGS` order, so when two
+/// actions share a key the earlier one wins.
+#[derive(Clone)]
+pub struct KeyMap {
+    bindings: Vec<(Action, VirtualKeyCode)>,
+}
+
+impl Default for KeyMap {
+    fn default() -> KeyMap {
+        KeyMap {
+            bindings: DEFAULT_BINDINGS.to_vec(),
+        }
+    }
+}
+
+impl KeyMap {
+    /// Reads the `[keymap]` section of the TOML config file at `path`, e.g. `quit = "Q"`. Key
+    /// names are winit's `VirtualKeyCode` variants. Unknown actions and keys are warned about and
+    /// leave the default binding in place.
+    pub fn load(path: &Path) -> io::Result<KeyMap> {
+        let config: Config = toml::from_str(&fs::read_to_string(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+
+        let mut keymap = KeyMap::default();
+        for (action_name, key_name) in &config.keymap {
+            let Ok(action) = Action::deserialize(str_deserializer(action_name)) else {
+                warn!("Unknown action '{}' in the keymap", action_name);
+                continue;
+            };
+            let Ok(key) = VirtualKeyCode::deserialize(str_deserializer(key_name)) else {
+                warn!(
+                    "Unknown key '{}' for '{}' in the keymap; keeping the default",
+                    key_name, action_name
+                );
+                continue;
+            };
+            keymap.bind(action, key);
+        }
+
+        for (i, (action, key)) in keymap.bindings.iter().enumerate() {
+            if let Some((earlier, _)) = keymap.bindings[..i].iter().find(|(_, k)| k == key) {
+                warn!(
+                    "{:?} and {:?} are both bound to {:?} in the keymap; only {:?} will trigger",
+                    earlier, action, key, earlier
+                );
+            }
+        }
+
+        Ok(keymap)
+    }
+
+    pub fn bind(&mut self, action: Action, key: VirtualKeyCode) {
+        if let Some(binding) = self.bindings.iter_mut().find(|(a, _)| *a == action) {
+            binding.1 = key;
+        }
+    }
+
+    pub fn key(&self, action: Action) -> VirtualKeyCode {
+        self.bindings
+            .iter()
+            .find(|(a, _)| *a == action)
+            .map(|(_, key)| *key)
+            .expect("every action has a binding")
+    }
+
+    pub fn action(&self, key: VirtualKeyCode) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(_, k)| *k == key)
+            .map(|(action, _)| *action)
+    }
+}
+
+fn str_deserializer(s: &str) -> StrDeserializer<'_, serde::de::value::Error> {
+    s.into_deserializer()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load_str(name: &str, config: &str) -> KeyMap {
+        let path = std::env::temp_dir().join(format!(
+            "ray-tracing-in-vulkano-keymap-{}-{}.toml",
+            std::process::id(),
+            name
+        ));
+        fs::write(&path, config).expect("failed to write the config file");
+        let keymap = KeyMap::load(&path);
+        let _ = fs::remove_file(&path);
+        keymap.expect("failed to load the keymap")
+    }
+
+    #[test]
+    fn overrides_replace_the_default_key() {
+        let keymap = load_str("override", "[keymap]\nquit = \"Q\"\n");
+        assert_eq!(keymap.key(Action::Quit), VirtualKeyCode::Q);
+        assert_eq!(keymap.action(VirtualKeyCode::Escape), None);
+        assert_eq!(keymap.key(Action::ToggleHeatmap), VirtualKeyCode::H);
+    }
+
+    #[test]
+    fn unknown_actions_and_keys_keep_the_defaults() {
+        let keymap = load_str(
+            "unknown",
+            "[keymap]\nteleport = \"T\"\ntoggle_heatmap = \"NotAKey\"\n",
+        );
+        assert_eq!(keymap.action(VirtualKeyCode::T), None);
+        assert_eq!(keymap.key(Action::ToggleHeatmap), VirtualKeyCode::H);
+    }
+
+    #[test]
+    fn the_earlier_action_wins_a_shared_key() {
+        let keymap = load_str("conflict", "[keymap]\ntoggle_lens = \"H\"\n");
+        assert_eq!(
+            keymap.action(VirtualKeyCode::H),
+            Some(Action::ToggleHeatmap)
+        );
+    }
+}
@@ -1,56 +1,262 @@
+use clap::{error::ErrorKind, CommandFactory, Parser};
+use keymap::KeyMap;
+use log::{error, warn, LevelFilter};
 use raytracer::RayTracer;
-use vulkano::swapchain::PresentMode;
+use std::{panic, path::PathBuf, sync::Arc, time::Duration};
+use vulkan::{
+    application::{create_instance, supported_present_modes, RenderResolution},
+    capture::FrameCapture,
+    device_info::DeviceInfo,
+    gpu_profiler::GpuProfiler,
+    input_log::{InputRecorder, InputReplay},
+};
+use vulkano::swapchain::{CompositeAlpha, PresentMode, Surface};
+use winit::{event_loop::EventLoop, window::WindowBuilder};
 
+mod benchmark;
+mod keymap;
 mod raytracer;
 mod vulkan;
 
+#[derive(Parser)]
+#[command(about = "Ray tracing in Vulkan, ported to vulkano.")]
 struct Options {
+    /// Run the application in benchmark mode.
+    #[arg(long)]
     pub benchmark: bool,
+    /// Log more detail (-v for debug, -vv for trace). Benchmark mode only logs warnings and errors
+    /// by default. RUST_LOG overrides this.
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+    /// A TOML config file; its [keymap] section maps actions (e.g. quit, toggle_heatmap) to key
+    /// names (e.g. "Escape", "H").
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+    /// Load the next scene once the sample or time limit is reached.
+    #[arg(long = "next-scenes", help_heading = "Benchmark options")]
     pub benchmark_next_scenes: bool,
+    /// The benchmark time limit per scene (in seconds).
+    #[arg(
+        long = "max-time",
+        default_value_t = 60,
+        help_heading = "Benchmark options"
+    )]
     pub benchmark_max_time: u32,
+    /// Frames rendered during the first seconds of each scene aren't measured, so that cold
+    /// caches and shader compilation don't skew the results.
+    #[arg(
+        long = "benchmark-warmup",
+        value_name = "SECONDS",
+        default_value_t = 0,
+        help_heading = "Benchmark options"
+    )]
+    pub benchmark_warmup: u32,
+    /// Also write the benchmark results to this file as JSON.
+    #[arg(
+        long,
+        value_name = "PATH",
+        requires = "benchmark",
+        help_heading = "Benchmark options"
+    )]
+    pub benchmark_json: Option<PathBuf>,
+    /// The number of ray samples per pixel.
+    #[arg(long, default_value_t = 8, help_heading = "Renderer options")]
     pub samples: u32,
+    /// Render at this width, independent of the window size (requires --internal-height).
+    #[arg(
+        long,
+        requires = "internal_height",
+        value_parser = clap::value_parser!(u32).range(1..),
+        help_heading = "Renderer options"
+    )]
+    pub internal_width: Option<u32>,
+    /// Render at this height, independent of the window size (requires --internal-width).
+    #[arg(
+        long,
+        requires = "internal_width",
+        value_parser = clap::value_parser!(u32).range(1..),
+        help_heading = "Renderer options"
+    )]
+    pub internal_height: Option<u32>,
+    /// Render at the window size times this factor, e.g. 2 to supersample or 0.5 for speed.
+    #[arg(
+        long,
+        conflicts_with = "internal_width",
+        value_parser = parse_render_scale,
+        help_heading = "Renderer options"
+    )]
+    pub render_scale: Option<f32>,
+    /// The maximum number of bounces per ray.
+    #[arg(long, default_value_t = 16, help_heading = "Renderer options")]
     pub bounces: u32,
+    /// Trace at most this many of --samples per frame, so that heavy sampling doesn't make input
+    /// lag (0 = uncapped). The rest are traced over the following frames.
+    #[arg(long, default_value_t = 0, help_heading = "Renderer options")]
+    pub max_samples_per_frame: u32,
+    /// The maximum number of accumulated ray samples per pixel.
+    #[arg(long, default_value_t = 65_536, help_heading = "Renderer options")]
     pub max_samples: u32,
+    /// The scene to start with.
+    #[arg(long = "scene", default_value_t = 1, help_heading = "Scene options")]
     pub scene_index: u32,
+    /// Explicitly set which Vulkan device ID is visible (can be repeated for multiple devices). If
+    /// unspecified, all devices are visible.
+    #[arg(long = "visible-device", help_heading = "Vulkan options")]
     pub visible_devices: Option<Vec<u32>>,
-    pub width: u32,
-    pub height: u32,
+    /// List the available Vulkan devices and exit.
+    #[arg(long, help_heading = "Vulkan options")]
+    pub list_devices: bool,
+    /// List the available monitors, with the indices --monitor takes, and exit.
+    #[arg(long, help_heading = "Window options")]
+    pub list_monitors: bool,
+    /// Print --list-devices output as JSON.
+    #[arg(long, requires = "list_devices", help_heading = "Vulkan options")]
+    pub json: bool,
+    /// The framebuffer width (default: 1280, or the monitor's resolution in fullscreen).
+    #[arg(long, help_heading = "Window options")]
+    pub width: Option<u32>,
+    /// The framebuffer height (default: 720, or the monitor's resolution in fullscreen).
+    #[arg(long, help_heading = "Window options")]
+    pub height: Option<u32>,
+    /// The present mode (0 = Immediate, 1 = MailBox, 2 = FIFO, 3 = FIFORelaxed).
+    #[arg(
+        long,
+        default_value_t = 2,
+        value_parser = clap::value_parser!(u32).range(0..=3),
+        help_heading = "Window options"
+    )]
     pub present_mode: u32,
+    /// How the window is blended with the desktop behind it (0 = Opaque, 1 = PreMultiplied, 2 =
+    /// PostMultiplied). Anything but opaque makes the background transparent, where the
+    /// compositor supports it; unsupported modes fall back to opaque.
+    #[arg(
+        long,
+        default_value_t = 0,
+        value_parser = clap::value_parser!(u32).range(0..=2),
+        help_heading = "Window options"
+    )]
+    pub composite_alpha: u32,
+    /// The color the window is cleared to before each frame is drawn, as "r,g,b" in [0, 1]
+    /// (default: dark gray).
+    #[arg(long, value_parser = parse_color, help_heading = "Window options")]
+    pub clear_color: Option<[f32; 3]>,
+    /// Toggle fullscreen vs windowed (default: windowed).
+    #[arg(long, help_heading = "Window options")]
     pub fullscreen: bool,
+    /// Cover the monitor with a borderless window at the desktop resolution, instead of switching
+    /// video modes like --fullscreen.
+    #[arg(long, conflicts_with = "fullscreen", help_heading = "Window options")]
+    pub borderless: bool,
+    /// The monitor to open the window or go fullscreen on (see --list-monitors; default: the
+    /// primary monitor).
+    #[arg(long, value_name = "INDEX", help_heading = "Window options")]
+    pub monitor: Option<usize>,
+    /// Low latency mode: present without vsync (overrides --present-mode with Immediate). Combine
+    /// with --fps-cap to keep power draw and heat down.
+    #[arg(long, help_heading = "Window options")]
+    pub vsync_off: bool,
+    /// Limit the frame rate by pacing frames on the CPU (0 = uncapped).
+    #[arg(long, default_value_t = 0, help_heading = "Window options")]
+    pub fps_cap: u32,
+    /// Seconds to wait for the GPU to finish a frame before warning; the application exits after
+    /// repeated timeouts.
+    #[arg(
+        long,
+        default_value_t = 10,
+        value_parser = clap::value_parser!(u64).range(1..),
+        help_heading = "Vulkan options"
+    )]
+    pub frame_timeout: u64,
+    /// Capture frame N with RenderDoc, when running under it.
+    #[arg(long, value_name = "N", help_heading = "Vulkan options")]
+    pub renderdoc: Option<u32>,
+    /// Time each pass on the GPU with timestamp queries and show the breakdown in the window
+    /// title.
+    #[arg(long, help_heading = "Debug options")]
+    pub gpu_profile: bool,
+    /// Record key presses and resizes, with the frame they happened at, to this file.
+    #[arg(long, value_name = "PATH", help_heading = "Debug options")]
+    pub record_input: Option<PathBuf>,
+    /// Replay an input log written by --record-input, ignoring live input other than Escape.
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with = "record_input",
+        help_heading = "Debug options"
+    )]
+    pub replay_input: Option<PathBuf>,
 }
 
-impl Default for Options {
-    fn default() -> Self {
-        Self {
-            benchmark: false,
-            benchmark_next_scenes: false,
-            benchmark_max_time: 60,
-            samples: 8,
-            bounces: 16,
-            max_samples: 65_536,
-            scene_index: 1,
-            visible_devices: None,
-            width: 1280,
-            height: 720,
-            present_mode: 2,
-            fullscreen: false,
+impl Options {
+    /// Checks the constraints between options that clap can't express on its own.
+    fn validate(&self) -> Result<(), clap::Error> {
+        // the scene would end before anything was measured.
+        if self.benchmark_warmup >= self.benchmark_max_time {
+            return Err(Options::command().error(
+                ErrorKind::ArgumentConflict,
+                format!(
+                    "--benchmark-warmup ({}) must be shorter than --max-time ({})",
+                    self.benchmark_warmup, self.benchmark_max_time
+                ),
+            ));
         }
+        Ok(())
     }
 }
 
+fn parse_color(s: &str) -> Result<[f32; 3], String> {
+    let channels = s
+        .split(',')
+        .map(|c| {
+            c.trim()
+                .parse::<f32>()
+                .map_err(|e| format!("'{}': {}", c, e))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match channels[..] {
+        [r, g, b] if channels.iter().all(|c| (0.0..=1.0).contains(c)) => Ok([r, g, b]),
+        [_, _, _] => Err("color channels must be in [0, 1]".into()),
+        _ => Err("expected a color as \"r,g,b\"".into()),
+    }
+}
+
+fn parse_render_scale(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(scale) if scale.is_finite() && scale > 0.0 => Ok(scale),
+        Ok(_) => Err("the render scale must be a positive number".into()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[derive(Clone)]
 pub struct UserSettings {
     pub benchmark: bool,
     pub benchmark_next_scenes: bool,
     pub benchmark_max_time: u32,
+    pub benchmark_warmup: u32,
+    pub benchmark_json: Option<PathBuf>,
     pub scene_index: usize,
     pub is_ray_traced: bool,
     pub accumulate_rays: bool,
     pub number_of_samples: u32,
+    /// Caps the samples traced per presented frame, spreading `number_of_samples` over several
+    /// frames so that each stays short enough for input to be responsive. 0 is uncapped.
+    pub max_samples_per_frame: u32,
     pub number_of_bounces: u32,
     pub max_number_of_samples: u32,
     pub field_of_view: f32,
     pub aperture: f32,
     pub focus_distance: f32,
+    /// Derive `field_of_view` and `aperture` from a physical lens instead of setting them
+    /// directly. Lengths are in mm, and scene units are taken to be meters.
+    pub use_focal_length: bool,
+    pub focal_length: f32,
+    pub sensor_width: f32,
+    pub f_number: f32,
+    /// Holds the sample index still, so every frame traces the same samples in place of the
+    /// accumulated ones and shows the same noise. Unfreezing starts accumulating afresh.
+    pub freeze_sample: bool,
     pub show_heatmap: bool,
     pub heatmap_scale: f32,
     pub show_settings: bool,
@@ -60,6 +266,40 @@ pub struct UserSettings {
 impl UserSettings {
     pub const FOV_MIN: f32 = 10.0;
     pub const FOV_MAX: f32 = 90.0;
+    pub const SAMPLES_MIN: u32 = 1;
+    pub const SAMPLES_MAX: u32 = 128;
+    pub const BOUNCES_MIN: u32 = 1;
+    pub const BOUNCES_MAX: u32 = 32;
+    pub const F_NUMBER_MIN: f32 = 0.7;
+    pub const F_NUMBER_MAX: f32 = 64.0;
+
+    /// Switches between the lens settings and setting the field of view and aperture directly.
+    /// The lens is matched to the current view when switching to it, so the image doesn't change.
+    pub fn set_use_focal_length(&mut self, use_focal_length: bool, aspect_ratio: f32) {
+        // with no field of view set yet (e.g. before a scene's camera), the lens keeps its
+        // focal length, and without an aperture it stops down as far as it goes.
+        if use_focal_length && !self.use_focal_length {
+            if self.field_of_view > 0.0 && self.field_of_view < 180.0 {
+                self.focal_length = field_of_view_to_focal_length(
+                    self.field_of_view,
+                    self.sensor_width,
+                    aspect_ratio,
+                );
+            }
+            self.f_number = (self.focal_length / (self.aperture * 1000.0))
+                .clamp(UserSettings::F_NUMBER_MIN, UserSettings::F_NUMBER_MAX);
+        }
+        self.use_focal_length = use_focal_length;
+    }
+
+    /// Sets `field_of_view` and `aperture` from the lens settings, if they're in use.
+    pub fn apply_focal_length(&mut self, aspect_ratio: f32) {
+        if self.use_focal_length {
+            self.field_of_view =
+                focal_length_to_field_of_view(self.focal_length, self.sensor_width, aspect_ratio);
+            self.aperture = self.focal_length / self.f_number / 1000.0;
+        }
+    }
 
     pub fn requires_accumulation_reset(&self, prev: &UserSettings) -> bool {
         return self.is_ray_traced != prev.is_ray_traced
@@ -71,21 +311,52 @@ impl UserSettings {
     }
 }
 
+/// The vertical field of view (in degrees) of a lens of `focal_length` on a sensor
+/// `sensor_width` wide, with the sensor height following from `aspect_ratio`.
+pub fn focal_length_to_field_of_view(
+    focal_length: f32,
+    sensor_width: f32,
+    aspect_ratio: f32,
+) -> f32 {
+    let sensor_height = sensor_width / aspect_ratio;
+    (2.0 * (sensor_height / (2.0 * focal_length)).atan()).to_degrees()
+}
+
+/// The inverse of [`focal_length_to_field_of_view`], for switching to the lens settings without
+/// changing the image.
+pub fn field_of_view_to_focal_length(
+    field_of_view: f32,
+    sensor_width: f32,
+    aspect_ratio: f32,
+) -> f32 {
+    let sensor_height = sensor_width / aspect_ratio;
+    sensor_height / (2.0 * (field_of_view.to_radians() / 2.0).tan())
+}
+
 impl From<&Options> for UserSettings {
     fn from(opts: &Options) -> Self {
         UserSettings {
             benchmark: opts.benchmark,
             benchmark_next_scenes: opts.benchmark_next_scenes,
             benchmark_max_time: opts.benchmark_max_time,
+            benchmark_warmup: opts.benchmark_warmup,
+            benchmark_json: opts.benchmark_json.clone(),
             scene_index: opts.scene_index as usize,
             is_ray_traced: true,
             accumulate_rays: true,
             number_of_samples: opts.samples,
+            max_samples_per_frame: opts.max_samples_per_frame,
             number_of_bounces: opts.bounces,
             max_number_of_samples: opts.max_samples,
             field_of_view: 0.0,
             aperture: 0.0,
             focus_distance: 0.0,
+            use_focal_length: false,
+            focal_length: 50.0,
+            // a full-frame sensor.
+            sensor_width: 36.0,
+            f_number: 8.0,
+            freeze_sample: false,
             show_heatmap: false,
             heatmap_scale: 1.5,
             show_settings: !opts.benchmark,
@@ -95,38 +366,122 @@ impl From<&Options> for UserSettings {
 }
 
 fn main() {
-    let options = Options::default();
+    let options = Options::parse();
+    if let Err(e) = options.validate() {
+        e.exit();
+    }
+
+    init_logger(&options);
+
+    if options.list_devices {
+        list_devices(options.json);
+        return;
+    }
+
+    if options.list_monitors {
+        list_monitors();
+        return;
+    }
+
+    let keymap = match &options.config {
+        Some(path) => match KeyMap::load(path) {
+            Ok(keymap) => keymap,
+            Err(e) => {
+                error!("Failed to load config '{}': {}", path.display(), e);
+                return;
+            }
+        },
+        None => KeyMap::default(),
+    };
+
     let settings = UserSettings::from(&options);
     let window_config = vulkan::WindowConfig {
         title: "Vulkan Window".into(),
         width: options.width,
         height: options.height,
-        cursor_disabled: options.benchmark && options.fullscreen,
+        cursor_disabled: options.benchmark && (options.fullscreen || options.borderless),
         fullscreen: options.fullscreen,
-        resizable: !options.fullscreen,
+        borderless: options.borderless,
+        monitor: options.monitor,
+        resizable: !options.fullscreen && !options.borderless,
+    };
+
+    let present_mode = match options.present_mode {
+        _ if options.vsync_off => PresentMode::Immediate,
+        0 => PresentMode::Immediate,
+        1 => PresentMode::Mailbox,
+        2 => PresentMode::Fifo,
+        3 => PresentMode::FifoRelaxed,
+        _ => panic!(),
+    };
+
+    let composite_alpha = match options.composite_alpha {
+        0 => CompositeAlpha::Opaque,
+        1 => CompositeAlpha::PreMultiplied,
+        2 => CompositeAlpha::PostMultiplied,
+        _ => panic!(),
     };
 
-    let application = match RayTracer::new(
+    let mut application = match RayTracer::new(
         settings,
         window_config,
-        match options.present_mode {
-            0 => PresentMode::Immediate,
-            1 => PresentMode::Mailbox,
-            2 => PresentMode::Fifo,
-            3 => PresentMode::FifoRelaxed,
-            _ => panic!(),
-        },
+        present_mode,
+        composite_alpha,
+        options.fps_cap,
+        Duration::from_secs(options.frame_timeout),
         &options.visible_devices,
     ) {
         Ok(rt) => rt,
         Err(e) => {
             let e_str = format!("{}", e).to_string();
             let e_str = e_str.replace("\n", "\n\t");
-            eprintln!("Failed to create application:\n\t{}", e_str);
+            error!("Failed to create the ray tracer:\n\t{}", e_str);
             return;
         }
     };
 
+    let render_resolution = match (options.internal_width, options.internal_height) {
+        (Some(width), Some(height)) => RenderResolution::Fixed([width, height]),
+        _ => match options.render_scale {
+            Some(scale) => RenderResolution::Scaled(scale),
+            None => RenderResolution::Window,
+        },
+    };
+    if render_resolution != RenderResolution::Window {
+        if let Err(e) = application
+            .application
+            .set_render_resolution(render_resolution)
+        {
+            error!("Failed to set the render resolution:\n\t{}", e);
+            return;
+        }
+    }
+
+    application.set_keymap(keymap);
+    if let Some(clear_color) = options.clear_color {
+        application.application.clear_color = clear_color;
+    }
+    application.application.capture = options.renderdoc.and_then(FrameCapture::new);
+    if options.gpu_profile {
+        application.application.gpu_profiler =
+            GpuProfiler::new(&application.application.graphics_queue);
+    }
+    if let Some(path) = &options.record_input {
+        match InputRecorder::create(path) {
+            Ok(recorder) => application.application.input_recorder = Some(recorder),
+            Err(e) => warn!("Failed to create input log '{}': {}", path.display(), e),
+        }
+    }
+    if let Some(path) = &options.replay_input {
+        match InputReplay::load(path) {
+            Ok(replay) => application.application.input_replay = Some(replay),
+            Err(e) => {
+                error!("Failed to load input log '{}': {}", path.display(), e);
+                return;
+            }
+        }
+    }
+
     print_vulkan_sdk_info();
     print_vulkan_instance_info(&application, options.benchmark);
     print_vulkan_layers_info(&application, options.benchmark);
@@ -136,6 +491,20 @@ fn main() {
     application.run();
 }
 
+fn init_logger(options: &Options) {
+    let level = match options.verbose {
+        0 if options.benchmark => LevelFilter::Warn,
+        0 => LevelFilter::Info,
+        1 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+
+    env_logger::Builder::new()
+        .filter_level(level)
+        .parse_default_env()
+        .init();
+}
+
 fn print_vulkan_sdk_info() {
     println!(
         "Vulkan SDK Header Version: {}",
@@ -173,44 +542,135 @@ fn print_vulkan_devices(app: &RayTracer, visible_devices: &Option<Vec<u32>>) {
 
     match app.application.instance.enumerate_physical_devices() {
         Err(e) => {
-            eprintln!(
+            error!(
                 "Failed to enumerate physical devices. Cannot print devices... {}",
                 e
             );
             return;
         }
         Ok(pds) => pds.for_each(|pd| {
-            let props = pd.properties();
+            let info = DeviceInfo::from(pd.as_ref());
 
-            if visible_devices.as_ref().map_or(false, |v| !v.contains(&props.device_id)) {
+            if visible_devices
+                .as_ref()
+                .map_or(false, |v| !v.contains(&info.device_id))
+            {
                 return;
             }
 
-            with_vendor_id_string(props.vendor_id, |vendor_id| {
-                println!(
-                    "- [{}] {} '{}' ({:?}; Vulkan: {}; Driver: {}, '{}' - {})",
-                    props.device_id,
-                    vendor_id,
-                    props.device_name,
-                    props.device_type,
-                    props.api_version,
-                    props
-                        .driver_name
-                        .as_ref()
-                        .unwrap_or(&"Unnamed Driver".into()),
-                    props
-                        .driver_info
-                        .as_ref()
-                        .unwrap_or(&"No Driver Info".into()),
-                    props.driver_version,
-                );
-            })
+            print_device_info(&info);
         }),
     }
 
     println!("");
 }
 
+fn print_device_info(info: &DeviceInfo) {
+    println!(
+        "- [{}] {} '{}' ({}; Vulkan: {}; Driver: {}, '{}' - {})",
+        info.device_id,
+        info.vendor,
+        info.name,
+        info.device_type,
+        info.vulkan_version,
+        info.driver_name.as_deref().unwrap_or("Unnamed Driver"),
+        info.driver_info.as_deref().unwrap_or("No Driver Info"),
+        info.driver_version,
+    );
+    if let Some(present_modes) = &info.present_modes {
+        println!("  present modes: {}", present_modes.join(", "));
+    }
+}
+
+/// Prints every monitor with its index, for `--list-monitors`.
+fn list_monitors() {
+    let event_loop = match panic::catch_unwind(EventLoop::new) {
+        Ok(event_loop) => event_loop,
+        Err(_) => {
+            error!("Could not connect to a display to list monitors");
+            return;
+        }
+    };
+
+    let primary = event_loop.primary_monitor();
+    println!("Monitors:");
+    for (index, monitor) in event_loop.available_monitors().enumerate() {
+        let size = monitor.size();
+        println!(
+            "- [{}] {} ({}x{}{}){}",
+            index,
+            monitor.name().unwrap_or_else(|| "Unknown".into()),
+            size.width,
+            size.height,
+            monitor
+                .refresh_rate_millihertz()
+                .map(|mhz| format!(" @ {:.2} Hz", mhz as f64 / 1000.0))
+                .unwrap_or_default(),
+            if Some(&monitor) == primary.as_ref() {
+                " primary"
+            } else {
+                ""
+            },
+        );
+    }
+}
+
+/// Prints every physical device without creating a window, for `--list-devices`.
+fn list_devices(json: bool) {
+    let instance = match create_instance() {
+        Ok(instance) => instance,
+        Err(e) => {
+            error!("Failed to create instance:\n\t{}", e);
+            return;
+        }
+    };
+
+    // winit panics rather than failing when there's no display to connect to (e.g. in CI), in
+    // which case the devices are listed without present modes.
+    let event_loop = panic::catch_unwind(EventLoop::new).ok();
+    let surface = event_loop.as_ref().and_then(|el| {
+        WindowBuilder::new()
+            .with_visible(false)
+            .build(el)
+            .ok()
+            .and_then(|w| {
+                vulkano_win::create_surface_from_winit(Arc::new(w), instance.clone()).ok()
+            })
+    });
+    if surface.is_none() {
+        warn!("Could not create a surface; present modes won't be listed");
+    }
+
+    let devices = match instance.enumerate_physical_devices() {
+        Ok(pds) => pds
+            .map(|pd| {
+                let mut info = DeviceInfo::from(pd.as_ref());
+                info.present_modes = surface.as_ref().map(|surface: &Arc<Surface>| {
+                    supported_present_modes(&pd, surface)
+                        .iter()
+                        .map(|m| format!("{:?}", m))
+                        .collect()
+                });
+                info
+            })
+            .collect::<Vec<_>>(),
+        Err(e) => {
+            error!("Failed to enumerate physical devices:\n\t{}", e);
+            return;
+        }
+    };
+
+    if json {
+        match serde_json::to_string_pretty(&devices) {
+            Ok(s) => println!("{}", s),
+            Err(e) => error!("Failed to serialize device info: {}", e),
+        }
+    } else {
+        println!("Vulkan Devices:");
+        devices.iter().for_each(print_device_info);
+    }
+}
+
 fn print_vulkan_swapchain_info(app: &RayTracer) {
     println!("Swapchain:");
     println!("- image count: {}", app.application.swapchain.image_count());
@@ -218,18 +678,79 @@ fn print_vulkan_swapchain_info(app: &RayTracer) {
         "- present mode: {:?}",
         app.application.swapchain.present_mode()
     );
+    println!(
+        "- composite alpha: {:?}",
+        app.application.swapchain.composite_alpha()
+    );
+    println!(
+        "- supported present modes: {:?}",
+        app.application.supported_present_modes()
+    );
     println!("");
 }
 
-fn with_vendor_id_string(vendor_id: u32, f: impl FnOnce(&str) -> ()) {
-    let s = match vendor_id {
-        0x1002 => "AMD",
-        0x1010 => "ImgTec",
-        0x10DE => "NVIDIA",
-        0x13B5 => "ARM",
-        0x5143 => "Qualcomm",
-        0x8086 => "INTEL",
-        _ => "UnknownVendor",
-    };
-    f(s);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> UserSettings {
+        UserSettings::from(&Options::parse_from(["ray-tracing-in-vulkano"]))
+    }
+
+    #[test]
+    fn render_resolution_options_must_be_positive() {
+        let parse = |args: &[&str]| {
+            Options::try_parse_from(["ray-tracing-in-vulkano"].iter().chain(args)).is_ok()
+        };
+        assert!(parse(&["--render-scale", "0.5"]));
+        assert!(!parse(&["--render-scale", "0"]));
+        assert!(!parse(&["--render-scale", "NaN"]));
+        assert!(parse(&["--internal-width=640", "--internal-height=360"]));
+        assert!(!parse(&["--internal-width=0", "--internal-height=360"]));
+    }
+
+    #[test]
+    fn benchmark_warmup_must_end_before_the_time_limit() {
+        let validate = |args: &[&str]| {
+            Options::parse_from(["ray-tracing-in-vulkano"].iter().chain(args))
+                .validate()
+                .is_ok()
+        };
+        assert!(validate(&[]));
+        assert!(validate(&["--benchmark-warmup=5", "--max-time=10"]));
+        assert!(!validate(&["--benchmark-warmup=10", "--max-time=10"]));
+        assert!(!validate(&["--benchmark-warmup=70"]));
+    }
+
+    #[test]
+    fn focal_length_round_trips_field_of_view() {
+        for field_of_view in [10.0, 40.0, 90.0] {
+            let focal_length = field_of_view_to_focal_length(field_of_view, 36.0, 16.0 / 9.0);
+            let round_trip = focal_length_to_field_of_view(focal_length, 36.0, 16.0 / 9.0);
+            assert!((round_trip - field_of_view).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn switching_to_the_lens_keeps_the_image() {
+        let mut settings = UserSettings {
+            field_of_view: 40.0,
+            aperture: 0.005,
+            ..settings()
+        };
+        settings.set_use_focal_length(true, 1.5);
+        settings.apply_focal_length(1.5);
+        assert!((settings.field_of_view - 40.0).abs() < 1e-3);
+        assert!((settings.aperture - 0.005).abs() < 1e-6);
+    }
+
+    #[test]
+    fn switching_to_the_lens_without_a_view_stays_finite() {
+        let mut settings = settings();
+        settings.set_use_focal_length(true, 1.5);
+        settings.apply_focal_length(1.5);
+        assert!(settings.field_of_view.is_finite() && settings.field_of_view > 0.0);
+        assert!(settings.aperture.is_finite());
+        assert!(!settings.requires_accumulation_reset(&settings.clone()));
+    }
 }
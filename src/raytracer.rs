@@ -1,16 +1,70 @@
-use vulkano::swapchain::PresentMode;
+use std::time::{Duration, Instant};
+
+use log::{error, info, warn};
+use vulkano::swapchain::{CompositeAlpha, PresentMode};
+use winit::event::VirtualKeyCode;
 
 use crate::{
+    benchmark::Benchmark,
+    keymap::{Action, KeyMap},
     vulkan::{
-        application::{Application, ApplicationCreationError},
+        application::{Application, ApplicationCreationError, ApplicationHandler},
+        device_info::DeviceInfo,
+        uniform_buffer::UniformBufferObject,
         WindowConfig,
     },
     UserSettings,
 };
 
+/// How quickly (in seconds) the smoothed sample rate follows the measured one.
+const SAMPLE_RATE_TIME_CONSTANT: f64 = 1.0;
+
+const TITLE_UPDATE_PERIOD: Duration = Duration::from_secs(1);
+
+#[derive(Default)]
+pub struct Statistics {
+    pub framebuffer_size: [u32; 2],
+    pub frame_rate: f32,
+    pub ray_rate: f32,
+    /// The samples traced this frame, which `max_samples_per_frame` may hold below the
+    /// requested number.
+    pub frame_samples: u32,
+    pub total_samples: u32,
+    pub max_samples: u32,
+    /// Accumulated samples per pixel per second, smoothed with an exponential moving average.
+    pub samples_per_second: f32,
+    /// The estimated time until `total_samples` reaches `max_samples`, if any progress is being
+    /// made.
+    pub time_to_convergence: Option<Duration>,
+    /// How long each pass took on the GPU (in milliseconds), when profiling with `--gpu-profile`.
+    pub gpu_pass_times: Vec<(String, f32)>,
+}
+
+impl Statistics {
+    /// The fraction of `max_samples` accumulated so far, in [0, 1].
+    pub fn progress(&self) -> f32 {
+        if self.max_samples == 0 {
+            return 1.0;
+        }
+        self.total_samples as f32 / self.max_samples as f32
+    }
+}
+
 pub struct RayTracer {
     pub application: Application,
     pub user_settings: UserSettings,
+    pub previous_settings: UserSettings,
+    /// The settings derived from the command line, restored by the reset key.
+    pub default_settings: UserSettings,
+    pub statistics: Statistics,
+    time: Instant,
+    total_number_of_samples: u32,
+    number_of_samples: u32,
+    reset_accumulation: bool,
+    title_time: Instant,
+    title_frames: u32,
+    keymap: KeyMap,
+    benchmark: Option<Benchmark>,
 }
 
 impl RayTracer {
@@ -18,15 +72,397 @@ impl RayTracer {
         user_settings: UserSettings,
         window_config: WindowConfig,
         present_mode: PresentMode,
+        composite_alpha: CompositeAlpha,
+        fps_cap: u32,
+        frame_timeout: Duration,
         visible_devices: &Option<Vec<u32>>,
-    ) -> Result<RayTracer, ApplicationCreationError> {
+    ) -> Result<RayTracer, RayTracerCreationError> {
         Ok(RayTracer {
-            application: Application::new(window_config, present_mode, visible_devices)?,
+            application: Application::new(
+                window_config,
+                present_mode,
+                composite_alpha,
+                fps_cap,
+                frame_timeout,
+                visible_devices,
+            )
+            .map_err(RayTracerCreationError::ApplicationCreationError)?,
+            previous_settings: user_settings.clone(),
+            default_settings: user_settings.clone(),
             user_settings,
+            statistics: Default::default(),
+            time: Instant::now(),
+            total_number_of_samples: 0,
+            number_of_samples: 0,
+            reset_accumulation: true,
+            title_time: Instant::now(),
+            title_frames: 0,
+            keymap: KeyMap::default(),
+            benchmark: None,
         })
     }
 
+    pub fn set_keymap(&mut self, keymap: KeyMap) {
+        self.application.quit_key = keymap.key(Action::Quit);
+        self.keymap = keymap;
+    }
+
     pub fn run(self) {
-        self.application.run();
+        info!(
+            "{:?}: settings, {:?}: overlay (both in the window title)",
+            self.keymap.key(Action::ToggleSettings),
+            self.keymap.key(Action::ToggleOverlay)
+        );
+        Application::run(self);
+    }
+
+    fn update_statistics(&mut self, time_delta: f64) {
+        let extent = self.application.render_extent();
+        let stats = &mut self.statistics;
+
+        stats.framebuffer_size = extent;
+        stats.frame_rate = (1.0 / time_delta) as f32;
+        stats.ray_rate = (extent[0] as f64 * extent[1] as f64 * self.number_of_samples as f64
+            / (time_delta * 1_000_000_000.0)) as f32;
+        stats.frame_samples = self.number_of_samples;
+        stats.total_samples = self.total_number_of_samples;
+        stats.max_samples = self.user_settings.max_number_of_samples;
+        if let Some(profiler) = &self.application.gpu_profiler {
+            stats.gpu_pass_times.clone_from(&profiler.pass_times);
+        }
+
+        // time based smoothing so the estimate settles at the same speed at any frame rate.
+        let sample_rate = self.number_of_samples as f64 / time_delta;
+        let alpha = 1.0 - (-time_delta / SAMPLE_RATE_TIME_CONSTANT).exp();
+        stats.samples_per_second +=
+            (alpha * (sample_rate - stats.samples_per_second as f64)) as f32;
+
+        let remaining_samples = stats.max_samples.saturating_sub(stats.total_samples);
+        stats.time_to_convergence = if remaining_samples == 0 {
+            Some(Duration::ZERO)
+        } else if self.user_settings.freeze_sample {
+            // nothing accumulates while the sample is frozen.
+            None
+        } else {
+            // None while the rate is zero, or so low that the estimate overflows.
+            Duration::try_from_secs_f32(remaining_samples as f32 / stats.samples_per_second).ok()
+        };
+    }
+
+    fn aspect_ratio(&self) -> f32 {
+        let [width, height] = self.application.render_extent();
+        width as f32 / height.max(1) as f32
+    }
+
+    /// The number of samples to trace each frame, capped by `max_samples_per_frame`.
+    fn samples_per_frame(&self) -> u32 {
+        match self.user_settings.max_samples_per_frame {
+            0 => self.user_settings.number_of_samples,
+            cap => self.user_settings.number_of_samples.min(cap),
+        }
+    }
+
+    fn is_converged(&self) -> bool {
+        self.user_settings.accumulate_rays && self.number_of_samples == 0
+    }
+
+    // TODO: camera matrices once the camera is ported.
+    fn uniform_buffer_object(&self) -> UniformBufferObject {
+        // the ray generation shader adds to the accumulation image unless both counts match. A
+        // frozen sample index traces the same samples every frame, so they overwrite the image
+        // instead of brightening it without bound.
+        let total_number_of_samples =
+            match self.user_settings.freeze_sample && self.number_of_samples > 0 {
+                true => self.number_of_samples,
+                false => self.total_number_of_samples,
+            };
+
+        UniformBufferObject {
+            aperture: self.user_settings.aperture,
+            focus_distance: self.user_settings.focus_distance,
+            heatmap_scale: self.user_settings.heatmap_scale,
+            total_number_of_samples,
+            number_of_samples: self.number_of_samples,
+            number_of_bounces: self.user_settings.number_of_bounces,
+            random_seed: 1,
+            has_sky: 1,
+            show_heatmap: self.user_settings.show_heatmap as u32,
+            ..Default::default()
+        }
+    }
+
+    fn update_window_title(&mut self) {
+        self.title_frames += 1;
+
+        let elapsed = self.time - self.title_time;
+        if elapsed < TITLE_UPDATE_PERIOD {
+            return;
+        }
+
+        let window = &self.application.window;
+        let settings = &self.user_settings;
+        let mut title = format!("{} — Scene #{}", window.config.title, settings.scene_index);
+
+        // the overlay: live statistics.
+        if settings.show_overlay {
+            let stats = &self.statistics;
+            let progress = match (self.is_converged(), stats.time_to_convergence) {
+                (true, _) => " (converged)".to_string(),
+                (false, Some(eta)) => format!(
+                    " ({:.0}%, {:.0} spp/s, ETA {})",
+                    stats.progress() * 100.0,
+                    stats.samples_per_second,
+                    format_duration(eta)
+                ),
+                (false, None) => format!(" ({:.0}%)", stats.progress() * 100.0),
+            };
+            let capped = match self.samples_per_frame() < settings.number_of_samples {
+                true => format!(" (+{} this frame)", stats.frame_samples),
+                false => String::new(),
+            };
+            title += &format!(
+                " — {:.0} fps — {} spp{}{}",
+                self.title_frames as f64 / elapsed.as_secs_f64(),
+                self.total_number_of_samples,
+                capped,
+                progress,
+            );
+            for (pass, ms) in &stats.gpu_pass_times {
+                title += &format!(" — {} {:.2} ms", pass, ms);
+            }
+        }
+
+        // the settings panel: what the keyboard changes.
+        if settings.show_settings {
+            title += &format!(
+                " — {} samples, {} bounces",
+                settings.number_of_samples, settings.number_of_bounces
+            );
+            if settings.use_focal_length {
+                title += &format!(" — {} mm f/{}", settings.focal_length, settings.f_number);
+            }
+            if settings.show_heatmap {
+                title += " — heatmap";
+            }
+        }
+
+        if settings.freeze_sample {
+            title += " — FROZEN SAMPLE";
+        }
+        window.set_title(&title);
+
+        self.title_time = self.time;
+        self.title_frames = 0;
+    }
+}
+
+impl ApplicationHandler for RayTracer {
+    fn application(&mut self) -> &mut Application {
+        &mut self.application
+    }
+
+    fn on_frame(&mut self) {
+        // Record delta time between frames.
+        let prev_time = self.time;
+        self.time = Instant::now();
+        let time_delta = (self.time - prev_time).as_secs_f64().max(f64::EPSILON);
+
+        let aspect_ratio = self.aspect_ratio();
+        self.user_settings.apply_focal_length(aspect_ratio);
+
+        // Check if the accumulation buffer needs to be reset.
+        if self.reset_accumulation
+            || self.application.render_extent() != self.statistics.framebuffer_size
+            || self
+                .user_settings
+                .requires_accumulation_reset(&self.previous_settings)
+            || !self.user_settings.accumulate_rays
+        {
+            self.total_number_of_samples = 0;
+            self.reset_accumulation = false;
+        }
+
+        self.previous_settings = self.user_settings.clone();
+
+        // Keep track of our sample count. A frozen sample index re-renders the same samples every
+        // frame, unless accumulation was just reset and there are none yet.
+        if !self.user_settings.freeze_sample || self.total_number_of_samples == 0 {
+            self.number_of_samples = self
+                .user_settings
+                .max_number_of_samples
+                .saturating_sub(self.total_number_of_samples)
+                .min(self.samples_per_frame());
+            self.total_number_of_samples += self.number_of_samples;
+        }
+
+        // Check the current state of the benchmark, update it for the new frame.
+        if self.user_settings.benchmark {
+            let scene_index = self.user_settings.scene_index;
+            let benchmark = self
+                .benchmark
+                .get_or_insert_with(|| Benchmark::new(scene_index));
+            if benchmark.update(
+                self.time,
+                self.time - prev_time,
+                self.number_of_samples,
+                &self.user_settings,
+            ) {
+                self.application.close();
+            }
+        }
+
+        self.update_statistics(time_delta);
+        self.update_window_title();
+
+        self.application.uniform_buffer_object = self.uniform_buffer_object();
+
+        // there's nothing left to accumulate until something resets it.
+        self.application.idle = self.is_converged();
+    }
+
+    fn on_key(&mut self, key: VirtualKeyCode) {
+        let Some(action) = self.keymap.action(key) else {
+            return;
+        };
+
+        // Panel visibility, also available in benchmark mode for debugging.
+        match action {
+            Action::ToggleSettings => {
+                self.user_settings.show_settings = !self.user_settings.show_settings;
+            }
+            Action::ToggleOverlay => {
+                self.user_settings.show_overlay = !self.user_settings.show_overlay;
+            }
+            _ => (),
+        }
+
+        // Settings (toggle switches)
+        if !self.user_settings.benchmark {
+            let aspect_ratio = self.aspect_ratio();
+            let settings = &mut self.user_settings;
+            match action {
+                Action::ToggleHeatmap => settings.show_heatmap = !settings.show_heatmap,
+                Action::ToggleFreezeSample => {
+                    settings.freeze_sample = !settings.freeze_sample;
+                    // the accumulation image only holds the frozen samples by now.
+                    if !settings.freeze_sample {
+                        self.reset_accumulation = true;
+                    }
+                    info!("Frozen sample: {}", settings.freeze_sample);
+                }
+                Action::ResetSettings => {
+                    // panel visibility isn't a render setting; leave it as it is.
+                    *settings = UserSettings {
+                        show_settings: settings.show_settings,
+                        show_overlay: settings.show_overlay,
+                        ..self.default_settings.clone()
+                    };
+                    self.reset_accumulation = true;
+                    info!("Settings reset to defaults");
+                }
+                Action::ToggleLens => {
+                    settings.set_use_focal_length(!settings.use_focal_length, aspect_ratio);
+                    match settings.use_focal_length {
+                        true => info!(
+                            "Lens: {:.0}mm f/{:.1}",
+                            settings.focal_length, settings.f_number
+                        ),
+                        false => info!("Lens: off"),
+                    }
+                }
+                Action::SamplesDown | Action::SamplesUp => {
+                    settings.number_of_samples = step_clamped(
+                        settings.number_of_samples,
+                        action == Action::SamplesUp,
+                        UserSettings::SAMPLES_MIN,
+                        UserSettings::SAMPLES_MAX,
+                    );
+                    info!("Samples: {}", settings.number_of_samples);
+                }
+                Action::BouncesDown | Action::BouncesUp => {
+                    settings.number_of_bounces = step_clamped(
+                        settings.number_of_bounces,
+                        action == Action::BouncesUp,
+                        UserSettings::BOUNCES_MIN,
+                        UserSettings::BOUNCES_MAX,
+                    );
+                    info!("Bounces: {}", settings.number_of_bounces);
+                }
+                // quitting is handled by the application; the panels above.
+                _ => (),
+            }
+        }
+    }
+
+    fn on_exit(&mut self) {
+        let (Some(benchmark), Some(path)) =
+            (&mut self.benchmark, &self.user_settings.benchmark_json)
+        else {
+            return;
+        };
+
+        // the benchmark may have been interrupted before reaching its limits.
+        benchmark.finish_scene(self.time);
+
+        let device = DeviceInfo::from(self.application.device.physical_device().as_ref());
+        let resolution = self.application.swapchain.image_extent();
+        match benchmark.write_report(path, device, resolution, &self.user_settings) {
+            Ok(()) => println!("Benchmark: Wrote report to '{}'", path.display()),
+            Err(e) => error!(
+                "Failed to write benchmark report to '{}': {}",
+                path.display(),
+                e
+            ),
+        }
+    }
+
+    fn on_frame_timeout(&mut self) {
+        let settings = &self.user_settings;
+        warn!(
+            "Frame timed out on scene #{} ({} samples, {} bounces, {} total samples)",
+            settings.scene_index,
+            settings.number_of_samples,
+            settings.number_of_bounces,
+            self.total_number_of_samples,
+        );
+    }
+}
+
+/// Formats `duration` as e.g. "1h05m", "3m20s" or "12s".
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        3600.. => format!("{}h{:02}m", secs / 3600, secs % 3600 / 60),
+        60.. => format!("{}m{:02}s", secs / 60, secs % 60),
+        _ => format!("{}s", secs),
+    }
+}
+
+fn step_clamped(value: u32, increase: bool, min: u32, max: u32) -> u32 {
+    let value = if increase {
+        value.saturating_add(1)
+    } else {
+        value.saturating_sub(1)
+    };
+    value.clamp(min, max)
+}
+
+/// The stage of `RayTracer::new` that failed.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum RayTracerCreationError {
+    /// Creating the window, device or swapchain failed.
+    ApplicationCreationError(ApplicationCreationError),
+    // TODO: SceneLoadError, PipelineCreationError and AccelerationStructureError, once scenes,
+    // the ray tracing pipeline and acceleration structures are ported.
+}
+impl std::fmt::Display for RayTracerCreationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RayTracerCreationError::ApplicationCreationError(e) => {
+                write!(f, "Window and device setup failed:\n{}", e)
+            }
+        }
     }
 }
+impl std::error::Error for RayTracerCreationError {}
@@ -0,0 +1,89 @@
+use std::sync::Arc;
+use vulkano::{
+    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferError, BufferUsage, Subbuffer},
+    device::Device,
+    format::Format,
+    image::{ImageCreateFlags, ImageDimensions, ImageError, ImageUsage, StorageImage},
+    memory::allocator::{AllocationCreateInfo, MemoryUsage, StandardMemoryAllocator},
+    DeviceSize,
+};
+
+/// Central place for allocating buffers and images, so that every subsystem allocates from the
+/// same pool instead of creating its own allocators. Cheap to clone.
+#[derive(Clone)]
+pub struct Allocator {
+    pub memory: Arc<StandardMemoryAllocator>,
+}
+
+impl Allocator {
+    pub fn new(device: Arc<Device>) -> Allocator {
+        Allocator {
+            memory: Arc::new(StandardMemoryAllocator::new_default(device)),
+        }
+    }
+
+    /// A buffer of `len` elements that only the GPU can access, e.g. vertex, index or
+    /// acceleration structure storage. Fill it with a transfer from a host visible buffer.
+    pub fn alloc_device_local_buffer<T: BufferContents>(
+        &self,
+        usage: BufferUsage,
+        len: DeviceSize,
+    ) -> Result<Subbuffer<[T]>, BufferError> {
+        Buffer::new_slice(
+            &self.memory,
+            BufferCreateInfo {
+                usage,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                usage: MemoryUsage::DeviceOnly,
+                ..Default::default()
+            },
+            len,
+        )
+    }
+
+    /// A buffer initialized with `data` that the CPU can keep writing to, e.g. uniforms or
+    /// staging data.
+    pub fn alloc_host_visible_buffer<T: BufferContents>(
+        &self,
+        usage: BufferUsage,
+        data: T,
+    ) -> Result<Subbuffer<T>, BufferError> {
+        Buffer::from_data(
+            &self.memory,
+            BufferCreateInfo {
+                usage,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                usage: MemoryUsage::Upload,
+                ..Default::default()
+            },
+            data,
+        )
+    }
+
+    /// A 2D image that shaders can write to, e.g. accumulation or output targets. `usage` is
+    /// added to `STORAGE`.
+    pub fn alloc_storage_image(
+        &self,
+        extent: [u32; 2],
+        format: Format,
+        usage: ImageUsage,
+        queue_family_indices: impl IntoIterator<Item = u32>,
+    ) -> Result<Arc<StorageImage>, ImageError> {
+        StorageImage::with_usage(
+            &self.memory,
+            ImageDimensions::Dim2d {
+                width: extent[0],
+                height: extent[1],
+                array_layers: 1,
+            },
+            format,
+            ImageUsage::STORAGE | usage,
+            ImageCreateFlags::empty(),
+            queue_family_indices,
+        )
+    }
+}
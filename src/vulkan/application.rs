@@ -1,88 +1,523 @@
-use super::{window::Window, WindowConfig};
-use std::{io::Cursor, sync::Arc};
+use super::{
+    allocator::Allocator,
+    capture::FrameCapture,
+    frame_pacer::FramePacer,
+    gpu_profiler::GpuProfiler,
+    input::Input,
+    input_log::{InputEvent, InputRecorder, InputReplay},
+    uniform_buffer::UniformBufferObject,
+    window::Window,
+    WindowConfig,
+};
+use log::{debug, error, info, warn};
+use std::{
+    io::Cursor,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use vulkano::{
+    buffer::{BufferError, BufferUsage, Subbuffer},
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, BlitImageInfo,
+        BuildError, ClearColorImageInfo, ClearError, CommandBufferBeginError,
+        CommandBufferExecError, CommandBufferExecFuture, CommandBufferUsage, CopyError,
+    },
     device::{
-        physical::{PhysicalDeviceError, PhysicalDeviceType},
-        Device, DeviceCreateInfo, DeviceCreationError, DeviceExtensions, QueueCreateInfo,
+        physical::{PhysicalDevice, PhysicalDeviceError, PhysicalDeviceType},
+        Device, DeviceCreateInfo, DeviceCreationError, DeviceExtensions, Queue, QueueCreateInfo,
         QueueFlags,
     },
-    image::ImageUsage,
-    instance::{Instance, InstanceCreateInfo, InstanceCreationError},
+    format::{ClearColorValue, Format, FormatFeatures, NumericType},
+    image::{ImageAccess, ImageError, ImageUsage, StorageImage, SwapchainImage},
+    instance::{
+        debug::DebugUtilsLabel, Instance, InstanceCreateInfo, InstanceCreationError,
+        InstanceExtensions,
+    },
+    sampler::Filter,
     swapchain::{
-        PresentMode, Surface, SurfaceCreationError, Swapchain, SwapchainCreateInfo,
-        SwapchainCreationError,
+        acquire_next_image, AcquireError, ColorSpace, CompositeAlpha, PresentFuture, PresentMode,
+        Surface, SurfaceCapabilities, SurfaceCreationError, Swapchain, SwapchainAcquireFuture,
+        SwapchainCreateInfo, SwapchainCreationError, SwapchainPresentInfo,
+    },
+    sync::{
+        self,
+        future::{FenceSignalFuture, JoinFuture},
+        FlushError, GpuFuture,
     },
     LoadingError, VulkanError, VulkanLibrary,
 };
 use winit::{
-    dpi::PhysicalSize,
+    dpi::{PhysicalPosition, PhysicalSize},
     error::OsError,
-    event::{Event, VirtualKeyCode, WindowEvent},
+    event::{Event, KeyboardInput, VirtualKeyCode, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
+    monitor::MonitorHandle,
     window::{Icon, WindowBuilder},
 };
 
+pub const MAX_FRAMES_IN_FLIGHT: usize = 2;
+
+/// How many frames in a row may exceed the frame timeout before giving up on the device.
+const MAX_FRAME_TIMEOUTS: u32 = 3;
+
+/// What the swapchain images are cleared to before drawing, unless set with `--clear-color`.
+pub const DEFAULT_CLEAR_COLOR: [f32; 3] = [0.1, 0.1, 0.1];
+
+/// How often an idle application redraws when no events wake it up.
+const IDLE_REDRAW_PERIOD: Duration = Duration::from_millis(250);
+
+/// The format of the offscreen output image, when the swapchain images can't be written to
+/// directly.
+const OUTPUT_FORMAT: Format = Format::R8G8B8A8_UNORM;
+
+/// Signaled once the GPU has finished a submitted frame.
+type FrameFence = Arc<
+    FenceSignalFuture<
+        PresentFuture<
+            CommandBufferExecFuture<JoinFuture<Box<dyn GpuFuture>, SwapchainAcquireFuture>>,
+        >,
+    >,
+>;
+
+/// Creates a Vulkan instance with the extensions needed to present to a window.
+pub fn create_instance() -> Result<Arc<Instance>, ApplicationCreationError> {
+    let library = VulkanLibrary::new().map_err(ApplicationCreationError::LoadingError)?;
+    let create_info = InstanceCreateInfo {
+        enabled_extensions: InstanceExtensions {
+            // only used to label command buffer regions for debuggers like RenderDoc.
+            ext_debug_utils: library.supported_extensions().ext_debug_utils,
+            ..vulkano_win::required_extensions(&library)
+        },
+        enumerate_portability: true,
+        ..Default::default()
+    };
+
+    // portability enumeration is only needed for MoltenVK; retry without it where it's missing.
+    match Instance::new(library.clone(), create_info.clone()) {
+        Ok(instance) => {
+            debug!("Created instance with portability enumeration.");
+            Ok(instance)
+        }
+        Err(
+            e @ (InstanceCreationError::ExtensionNotPresent
+            | InstanceCreationError::RequirementNotMet { .. }),
+        ) => {
+            debug!(
+                "Creating instance with portability enumeration failed ({}), retrying without it.",
+                e
+            );
+            Instance::new(
+                library,
+                InstanceCreateInfo {
+                    enumerate_portability: false,
+                    ..create_info
+                },
+            )
+            .map(|instance| {
+                info!("Created instance without portability enumeration.");
+                instance
+            })
+            .map_err(ApplicationCreationError::InstanceCreationError)
+        }
+        Err(e) => Err(ApplicationCreationError::InstanceCreationError(e)),
+    }
+}
+
+fn alloc_output_image(
+    allocator: &Allocator,
+    extent: [u32; 2],
+    queue: &Queue,
+) -> Result<Arc<StorageImage>, ImageError> {
+    allocator.alloc_storage_image(
+        extent,
+        OUTPUT_FORMAT,
+        ImageUsage::TRANSFER_SRC | ImageUsage::TRANSFER_DST,
+        [queue.queue_family_index()],
+    )
+}
+
+/// Whether shaders can write the offscreen output image and blit it into swapchain images of
+/// `swapchain_format`, as `OutputStrategy::Blit` does.
+fn supports_blit(
+    physical_device: &PhysicalDevice,
+    swapchain_format: Format,
+) -> Result<bool, PhysicalDeviceError> {
+    let output_features = physical_device
+        .format_properties(OUTPUT_FORMAT)?
+        .optimal_tiling_features;
+    let swapchain_features = physical_device
+        .format_properties(swapchain_format)?
+        .optimal_tiling_features;
+    Ok(
+        output_features.contains(FormatFeatures::STORAGE_IMAGE | FormatFeatures::BLIT_SRC)
+            && swapchain_features.contains(FormatFeatures::BLIT_DST),
+    )
+}
+
+/// What device selection looks at, split out of [`PhysicalDevice`] so that it can be tested.
+struct DeviceCandidate {
+    device_id: u32,
+    device_type: PhysicalDeviceType,
+    has_required_extensions: bool,
+    has_swapchain: bool,
+    max_geometry_count: Option<u64>,
+}
+
+impl DeviceCandidate {
+    fn new(physical_device: &PhysicalDevice, required_extensions: &DeviceExtensions) -> Self {
+        let props = physical_device.properties();
+        DeviceCandidate {
+            device_id: props.device_id,
+            device_type: props.device_type,
+            has_required_extensions: physical_device
+                .supported_extensions()
+                .contains(required_extensions),
+            has_swapchain: physical_device.supported_extensions().khr_swapchain,
+            max_geometry_count: props.max_geometry_count,
+        }
+    }
+}
+
+/// Ranks device types by preference; lower is better.
+fn score_device(device_type: PhysicalDeviceType) -> u32 {
+    match device_type {
+        PhysicalDeviceType::DiscreteGpu => 0,
+        PhysicalDeviceType::IntegratedGpu => 1,
+        PhysicalDeviceType::VirtualGpu => 2,
+        PhysicalDeviceType::Cpu => 3,
+        PhysicalDeviceType::Other => 4,
+        _ => 5,
+    }
+}
+
+/// The index of the best candidate that supports ray tracing and isn't hidden by
+/// `visible_devices`.
+fn choose_device(
+    candidates: &[DeviceCandidate],
+    visible_devices: &Option<Vec<u32>>,
+) -> Option<usize> {
+    candidates
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| {
+            c.has_required_extensions
+                && c.max_geometry_count.is_some_and(|count| count > 0)
+                && is_visible(c, visible_devices)
+        })
+        .min_by_key(|(_, c)| score_device(c.device_type))
+        .map(|(i, _)| i)
+}
+
+/// The index of a CPU implementation (e.g. lavapipe) to fall back to when no device supports
+/// ray tracing, so that the application still runs in CI containers and VMs. Only used when
+/// every visible device is a CPU one; a real GPU without ray tracing should be reported rather
+/// than silently swapped for something orders of magnitude slower.
+fn choose_software_device(
+    candidates: &[DeviceCandidate],
+    visible_devices: &Option<Vec<u32>>,
+) -> Option<usize> {
+    let mut visible = candidates
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| is_visible(c, visible_devices));
+    if visible
+        .clone()
+        .any(|(_, c)| c.device_type != PhysicalDeviceType::Cpu)
+    {
+        return None;
+    }
+    visible.find(|(_, c)| c.has_swapchain).map(|(i, _)| i)
+}
+
+fn is_visible(candidate: &DeviceCandidate, visible_devices: &Option<Vec<u32>>) -> bool {
+    !visible_devices
+        .as_ref()
+        .is_some_and(|v| !v.contains(&candidate.device_id))
+}
+
+/// The swapchain extent for a window of `requested` size. Some platforms dictate the extent
+/// through `current_extent`; otherwise the requested size is clamped to the supported range.
+fn choose_image_extent(requested: [u32; 2], capabilities: &SurfaceCapabilities) -> [u32; 2] {
+    let extent = capabilities.current_extent.unwrap_or([
+        requested[0].clamp(
+            capabilities.min_image_extent[0],
+            capabilities.max_image_extent[0],
+        ),
+        requested[1].clamp(
+            capabilities.min_image_extent[1],
+            capabilities.max_image_extent[1],
+        ),
+    ]);
+    if extent != requested {
+        info!(
+            "Using a {}x{} swapchain instead of the requested {}x{}",
+            extent[0], extent[1], requested[0], requested[1]
+        );
+    }
+    extent
+}
+
+/// Updates the input state with a key event. The handler gets the key presses once per frame,
+/// through [`ApplicationHandler::on_key`].
+fn handle_key(
+    handler: &mut impl ApplicationHandler,
+    input: &KeyboardInput,
+    control_flow: &mut ControlFlow,
+) {
+    let app = handler.application();
+    app.input.update(input);
+    if app.input.just_pressed(app.quit_key) {
+        *control_flow = ControlFlow::Exit;
+    }
+}
+
+/// The position that centers a `width` x `height` window on `monitor`.
+fn centered_on(monitor: &MonitorHandle, width: u32, height: u32) -> PhysicalPosition<i32> {
+    let position = monitor.position();
+    let size = monitor.size();
+    PhysicalPosition::new(
+        position.x + (size.width as i32 - width as i32).max(0) / 2,
+        position.y + (size.height as i32 - height as i32).max(0) / 2,
+    )
+}
+
+/// The present modes `surface` supports on `physical_device`, or none if they can't be queried.
+pub fn supported_present_modes(
+    physical_device: &PhysicalDevice,
+    surface: &Surface,
+) -> Vec<PresentMode> {
+    match physical_device.surface_present_modes(surface) {
+        Ok(modes) => modes.collect(),
+        Err(e) => {
+            warn!("Failed to query supported present modes: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Picks `requested` if it's supported, otherwise the closest supported alternative. FIFO is the
+/// only present mode every surface has to support.
+fn choose_present_mode(requested: PresentMode, supported: &[PresentMode]) -> PresentMode {
+    let fallbacks: &[PresentMode] = match requested {
+        PresentMode::Immediate => &[PresentMode::Mailbox],
+        PresentMode::Mailbox => &[PresentMode::Immediate],
+        _ => &[],
+    };
+
+    std::iter::once(requested)
+        .chain(fallbacks.iter().copied())
+        .find(|m| supported.contains(m))
+        .unwrap_or(PresentMode::Fifo)
+}
+
+/// Prefers `B8G8R8A8_SRGB`, then any sRGB format, so the output is gamma correct without a
+/// conversion pass, and falls back to the first format. `None` if there are no formats at all.
+fn choose_surface_format(formats: &[(Format, ColorSpace)]) -> Option<(Format, ColorSpace)> {
+    let is_srgb = |&&(format, color_space): &&(Format, ColorSpace)| {
+        format.type_color() == Some(NumericType::SRGB) && color_space == ColorSpace::SrgbNonLinear
+    };
+
+    formats
+        .iter()
+        .find(|f| is_srgb(f) && f.0 == Format::B8G8R8A8_SRGB)
+        .or_else(|| formats.iter().find(is_srgb))
+        .or(formats.first())
+        .copied()
+}
+
+/// Picks `requested` if it's supported, otherwise opaque, otherwise whatever the surface offers
+/// first.
+fn choose_composite_alpha(
+    requested: CompositeAlpha,
+    supported: &[CompositeAlpha],
+) -> Option<CompositeAlpha> {
+    [requested, CompositeAlpha::Opaque]
+        .into_iter()
+        .find(|a| supported.contains(a))
+        .or(supported.first().copied())
+}
+
+/// The hooks through which a renderer built on top of [`Application`] takes part in the event
+/// loop, like the virtual `On*` methods of the original C++ `Application`.
+pub trait ApplicationHandler {
+    fn application(&mut self) -> &mut Application;
+
+    /// Called once per frame, before it is drawn.
+    fn on_frame(&mut self) {}
+
+    /// Called before `on_frame` for each key pressed since the last frame, once per press no
+    /// matter how long it's held; see [`Application::input`] for the keys held down.
+    fn on_key(&mut self, _key: VirtualKeyCode) {}
+
+    /// Called when the GPU didn't finish a frame within the frame timeout.
+    fn on_frame_timeout(&mut self) {}
+
+    /// Called once when the event loop exits, for whatever reason.
+    fn on_exit(&mut self) {}
+}
+
+/// How the ray traced image gets into the swapchain images.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OutputStrategy {
+    /// Shaders write straight into the swapchain images.
+    Storage,
+    /// Shaders write into an offscreen storage image, which is then blitted into the swapchain
+    /// image. Used when the surface doesn't support storage usage or has an sRGB format.
+    Blit,
+}
+
+/// The resolution the ray tracer renders at, which is scaled to the window when presenting.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RenderResolution {
+    /// Follow the swapchain extent.
+    Window,
+    /// A fixed extent that window resizes don't change.
+    Fixed([u32; 2]),
+    /// The swapchain extent multiplied by a factor, e.g. 2.0 to supersample.
+    Scaled(f32),
+}
+
+impl RenderResolution {
+    pub fn extent(&self, swapchain_extent: [u32; 2]) -> [u32; 2] {
+        match *self {
+            RenderResolution::Window => swapchain_extent,
+            RenderResolution::Fixed(extent) => extent,
+            RenderResolution::Scaled(scale) => {
+                swapchain_extent.map(|e| ((e as f32 * scale).round() as u32).max(1))
+            }
+        }
+    }
+}
+
 pub struct Application {
-    pub event_loop: EventLoop<()>,
+    pub event_loop: Option<EventLoop<()>>,
     pub present_mode: PresentMode,
+    pub frame_pacer: FramePacer,
+    pub capture: Option<FrameCapture>,
+    pub gpu_profiler: Option<GpuProfiler>,
+    pub input_recorder: Option<InputRecorder>,
+    /// While replaying, live keyboard input other than `quit_key` is ignored.
+    pub input_replay: Option<InputReplay>,
+    /// The number of frames drawn so far, which input logs are keyed by.
+    pub frame_index: u64,
+    /// Exits the event loop when pressed (Escape unless remapped).
+    pub quit_key: VirtualKeyCode,
+    pub input: Input,
     pub window: Window,
     pub instance: Arc<Instance>,
     pub surface: Arc<Surface>,
     pub device: Arc<Device>,
+    pub graphics_queue: Arc<Queue>,
+    pub compute_queue: Arc<Queue>,
     pub swapchain: Arc<Swapchain>,
-    pub uniform_buffers: Vec<usize>,            // TODO
-    pub depth_buffer: usize,                    // TODO
-    pub graphics_pipeline: usize,               // TODO
-    pub swapchain_frame_buffers: Vec<usize>,    // TODO
-    pub command_pool: usize,                    // TODO
+    pub swapchain_images: Vec<Arc<SwapchainImage>>,
+    pub output_strategy: OutputStrategy,
+    pub render_resolution: RenderResolution,
+    /// The offscreen image of `OutputStrategy::Blit`, sized by `render_resolution`.
+    pub output_image: Option<Arc<StorageImage>>,
+    /// The color each swapchain image is cleared to before anything is drawn into it.
+    pub clear_color: [f32; 3],
+    /// Written to `uniform_buffers[current_frame]` once the GPU is done with that slot.
+    pub uniform_buffer_object: UniformBufferObject,
+    pub uniform_buffers: Vec<Subbuffer<UniformBufferObject>>,
+    pub depth_buffer: usize,                 // TODO
+    pub graphics_pipeline: usize,            // TODO
+    pub swapchain_frame_buffers: Vec<usize>, // TODO
+    pub allocator: Allocator,
+    pub command_buffer_allocator: StandardCommandBufferAllocator,
     pub command_buffers: usize,                 // TODO
     pub image_available_semaphores: Vec<usize>, // TODO
     pub render_finished_semaphores: Vec<usize>, // TODO
-    pub in_flight_fences: Vec<usize>,           // TODO
+    pub in_flight_fences: Vec<Option<FrameFence>>,
     pub current_frame: usize,
+    pub frame_timeout: Duration,
+    pub frame_timeouts: u32,
+    pub recreate_swapchain: bool,
+    /// The window has a zero-sized surface, so nothing can be presented to it.
+    pub minimized: bool,
+    /// Set by the handler when redrawing at full speed wouldn't change the image, e.g. once
+    /// accumulation has converged. Any window event still triggers a redraw right away.
+    pub idle: bool,
+    close_requested: bool,
 }
 
 impl Application {
     pub fn new(
         window_config: WindowConfig,
         present_mode: PresentMode,
+        composite_alpha: CompositeAlpha,
+        fps_cap: u32,
+        frame_timeout: Duration,
         visible_devices: &Option<Vec<u32>>,
     ) -> Result<Application, ApplicationCreationError> {
         // mostly taken from vulkano examples.
 
-        let library = VulkanLibrary::new().map_err(ApplicationCreationError::LoadingError)?;
-        let required_extensions = vulkano_win::required_extensions(&library);
-        let instance = Instance::new(
-            library,
-            InstanceCreateInfo {
-                enabled_extensions: required_extensions,
-                enumerate_portability: true,
-                ..Default::default()
-            },
-        )
-        .map_err(ApplicationCreationError::InstanceCreationError)?;
+        let instance = create_instance()?;
 
         let el = EventLoop::new();
 
-        let fullscreen = if window_config.fullscreen {
-            Some(winit::window::Fullscreen::Exclusive({
-                let video_mode = el
-                    .primary_monitor()
-                    .ok_or(ApplicationCreationError::NoPrimaryMonitorError)?
-                    .video_modes()
-                    .filter(|vm| {
-                        // enforce window size is what Vulkan expects
-                        vm.size().eq(&PhysicalSize {
-                            width: window_config.width,
-                            height: window_config.height,
-                        })
-                    })
-                    .max()
-                    .ok_or(ApplicationCreationError::NoVideoModeError)?;
-                video_mode
-            }))
+        let chosen_monitor = window_config.monitor.and_then(|index| {
+            let monitor = el.available_monitors().nth(index);
+            if monitor.is_none() {
+                warn!(
+                    "Monitor {} not found; using the primary monitor instead.",
+                    index
+                );
+            }
+            monitor
+        });
+
+        let (fullscreen, width, height) = if window_config.fullscreen {
+            let monitor = chosen_monitor
+                .clone()
+                .or_else(|| el.primary_monitor())
+                .ok_or(ApplicationCreationError::NoPrimaryMonitorError)?;
+            let native = monitor.size();
+            let width = window_config.width.unwrap_or(native.width);
+            let height = window_config.height.unwrap_or(native.height);
+            let refresh_rate = monitor.refresh_rate_millihertz();
+            let video_mode = monitor
+                .video_modes()
+                // enforce window size is what Vulkan expects
+                .filter(|vm| vm.size().eq(&PhysicalSize { width, height }))
+                // prefer the monitor's current refresh rate, then the best remaining mode.
+                .max_by_key(|vm| {
+                    (
+                        Some(vm.refresh_rate_millihertz()) == refresh_rate,
+                        vm.clone(),
+                    )
+                })
+                .ok_or(ApplicationCreationError::NoVideoModeError)?;
+            info!(
+                "Fullscreen video mode: {}x{} @ {:.2} Hz",
+                width,
+                height,
+                video_mode.refresh_rate_millihertz() as f64 / 1000.0,
+            );
+            (
+                Some(winit::window::Fullscreen::Exclusive(video_mode)),
+                width,
+                height,
+            )
+        } else if window_config.borderless {
+            let monitor = chosen_monitor
+                .clone()
+                .or_else(|| el.primary_monitor())
+                .ok_or(ApplicationCreationError::NoPrimaryMonitorError)?;
+            // the desktop keeps its video mode, so the window has to match it.
+            let size = monitor.size();
+            info!("Borderless fullscreen: {}x{}", size.width, size.height);
+            (
+                Some(winit::window::Fullscreen::Borderless(Some(monitor))),
+                size.width,
+                size.height,
+            )
         } else {
-            None
+            (
+                None,
+                window_config.width.unwrap_or(WindowConfig::DEFAULT_WIDTH),
+                window_config.height.unwrap_or(WindowConfig::DEFAULT_HEIGHT),
+            )
         };
 
         let icon = image::io::Reader::new(Cursor::new(
@@ -98,16 +533,20 @@ impl Application {
             Icon::from_rgba(i.into_vec(), width, height).ok()
         });
 
+        let mut window_builder = WindowBuilder::new()
+            .with_resizable(window_config.resizable)
+            .with_title(window_config.title.clone())
+            .with_inner_size(winit::dpi::PhysicalSize::new(width, height))
+            .with_window_icon(icon)
+            // the compositor only blends a swapchain with the desktop behind a transparent window.
+            .with_transparent(composite_alpha != CompositeAlpha::Opaque);
+        if fullscreen.is_some() {
+            window_builder = window_builder.with_fullscreen(fullscreen);
+        } else if let Some(monitor) = &chosen_monitor {
+            window_builder = window_builder.with_position(centered_on(monitor, width, height));
+        }
         let window = Arc::new(
-            WindowBuilder::new()
-                .with_resizable(window_config.resizable)
-                .with_title(window_config.title.clone())
-                .with_inner_size(winit::dpi::PhysicalSize::new(
-                    window_config.width,
-                    window_config.height,
-                ))
-                .with_fullscreen(fullscreen)
-                .with_window_icon(icon)
+            window_builder
                 .build(&el)
                 .map_err(ApplicationCreationError::OsError)?,
         );
@@ -119,30 +558,63 @@ impl Application {
             khr_swapchain: true,
             khr_ray_tracing_pipeline: true,
             khr_acceleration_structure: true,
+            // acceleration structures are built on the device, so deferred host operations go
+            // unused, but VK_KHR_acceleration_structure lists the extension as a dependency and
+            // device creation fails without it.
             khr_deferred_host_operations: true,
             khr_shader_clock: true,
             ..DeviceExtensions::empty()
         };
 
-        let physical_device = instance
+        let physical_devices: Vec<_> = instance
             .enumerate_physical_devices()
             .map_err(ApplicationCreationError::VulkanError)?
-            .filter(|p| {
-                p.supported_extensions().contains(&device_extensions)
-                    && p.properties().max_geometry_count.is_some_and(|c| c > 0)
-                    && !visible_devices
-                        .as_ref()
-                        .is_some_and(|v| !v.contains(&p.properties().device_id))
-            })
-            .min_by_key(|p| match p.properties().device_type {
-                PhysicalDeviceType::DiscreteGpu => 0,
-                PhysicalDeviceType::IntegratedGpu => 1,
-                PhysicalDeviceType::VirtualGpu => 2,
-                PhysicalDeviceType::Cpu => 3,
-                PhysicalDeviceType::Other => 4,
-                _ => 5,
-            })
-            .ok_or(ApplicationCreationError::NoPhysicalDevicesError)?;
+            .collect();
+
+        // a mistyped --visible-device would otherwise surface as the misleading
+        // NoPhysicalDevicesError below.
+        if let Some(visible_devices) = visible_devices {
+            let available: Vec<u32> = physical_devices
+                .iter()
+                .map(|p| p.properties().device_id)
+                .collect();
+            if !physical_devices.is_empty()
+                && !available.iter().any(|id| visible_devices.contains(id))
+            {
+                return Err(ApplicationCreationError::NoVisibleDevicesError(available));
+            }
+        }
+
+        let candidates: Vec<_> = physical_devices
+            .iter()
+            .map(|p| DeviceCandidate::new(p, &device_extensions))
+            .collect();
+        let (physical_device, device_extensions) = match choose_device(&candidates, visible_devices)
+        {
+            Some(i) => (physical_devices[i].clone(), device_extensions),
+            None => {
+                let physical_device = choose_software_device(&candidates, visible_devices)
+                    .map(|i| physical_devices[i].clone())
+                    .ok_or(ApplicationCreationError::NoPhysicalDevicesError)?;
+                warn!(
+                    "No device supports ray tracing; falling back to the software implementation \
+                     '{}'. PERFORMANCE WILL BE VERY LOW. This is only meant for smoke testing.",
+                    physical_device.properties().device_name
+                );
+                // enable whatever ray tracing support the implementation does have.
+                let device_extensions = physical_device
+                    .supported_extensions()
+                    .intersection(&device_extensions);
+                (physical_device, device_extensions)
+            }
+        };
+
+        info!(
+            "Using physical device [{}] '{}' ({:?})",
+            physical_device.properties().device_id,
+            physical_device.properties().device_name,
+            physical_device.properties().device_type,
+        );
 
         let mut found_graphics = false;
         let mut found_compute = false;
@@ -182,6 +654,11 @@ impl Application {
             .ok_or(ApplicationCreationError::NoComputeQueueError)?
             .0;
 
+        debug!(
+            "Using queue families {} (graphics) and {} (compute)",
+            graphics_queue_family_index, compute_queue_family_index
+        );
+
         let (device, mut queues) = Device::new(
             physical_device,
             DeviceCreateInfo {
@@ -201,57 +678,158 @@ impl Application {
         )
         .map_err(ApplicationCreationError::DeviceCreationError)?;
 
-        let _graphics_queue = queues
+        let graphics_queue = queues
             .next()
             .ok_or(ApplicationCreationError::NoGraphicsQueueError)?;
-        if _graphics_queue.queue_family_index() != graphics_queue_family_index {
+        if graphics_queue.queue_family_index() != graphics_queue_family_index {
             return Err(ApplicationCreationError::NoGraphicsQueueError);
         }
 
-        let _compute_queue = queues
+        let compute_queue = queues
             .next()
             .ok_or(ApplicationCreationError::NoComputeQueueError)?;
-        if _compute_queue.queue_family_index() != compute_queue_family_index {
+        if compute_queue.queue_family_index() != compute_queue_family_index {
             return Err(ApplicationCreationError::NoComputeQueueError);
         }
 
-        let (swapchain, _images) = {
+        let present_mode = {
+            let supported = supported_present_modes(device.physical_device(), &surface);
+            let chosen = choose_present_mode(present_mode, &supported);
+            if chosen != present_mode {
+                warn!(
+                    "Present mode {:?} is not supported (supported: {:?}); using {:?} instead",
+                    present_mode, supported, chosen
+                );
+            }
+            chosen
+        };
+
+        let (swapchain, swapchain_images, output_strategy) = {
             let surface_capabilities = device
                 .physical_device()
                 .surface_capabilities(&surface, Default::default())
                 .map_err(ApplicationCreationError::PhysicalDeviceError)?;
 
-            let image_format = Some(
-                device
-                    .physical_device()
-                    .surface_formats(&surface, Default::default())
-                    .map_err(ApplicationCreationError::PhysicalDeviceError)?[0]
-                    .0,
-            );
+            let surface_formats = device
+                .physical_device()
+                .surface_formats(&surface, Default::default())
+                .map_err(ApplicationCreationError::PhysicalDeviceError)?;
+            let (image_format, image_color_space) = choose_surface_format(&surface_formats)
+                .ok_or(ApplicationCreationError::NoSupportedSurfaceFormatsError)?;
+
+            // clearing and blitting both need transfer writes; storage is only a bonus.
+            let supported_usage = surface_capabilities.supported_usage_flags;
+            if !supported_usage.contains(ImageUsage::TRANSFER_DST) {
+                return Err(ApplicationCreationError::NoSupportedImageUsageError);
+            }
+            // the surface may allow storage usage while the format doesn't, e.g. for sRGB formats.
+            let image_format_features = device
+                .physical_device()
+                .format_properties(image_format)
+                .map_err(ApplicationCreationError::PhysicalDeviceError)?
+                .optimal_tiling_features;
+            let output_strategy = if supported_usage.contains(ImageUsage::STORAGE)
+                && image_format.type_color() != Some(NumericType::SRGB)
+                && image_format_features.contains(FormatFeatures::STORAGE_IMAGE)
+            {
+                OutputStrategy::Storage
+            } else if supports_blit(device.physical_device(), image_format)
+                .map_err(ApplicationCreationError::PhysicalDeviceError)?
+            {
+                OutputStrategy::Blit
+            } else {
+                return Err(ApplicationCreationError::NoSupportedOutputStrategyError);
+            };
+            let image_usage = match output_strategy {
+                OutputStrategy::Storage => {
+                    ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSFER_DST | ImageUsage::STORAGE
+                }
+                OutputStrategy::Blit => ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSFER_DST,
+            };
+
+            let composite_alpha = {
+                let supported: Vec<_> = surface_capabilities
+                    .supported_composite_alpha
+                    .into_iter()
+                    .collect();
+                let chosen = choose_composite_alpha(composite_alpha, &supported)
+                    .ok_or(ApplicationCreationError::NoSupportedCompositeAlphasError)?;
+                if chosen != composite_alpha {
+                    warn!(
+                        "Composite alpha {:?} is not supported (supported: {:?}); using {:?} \
+                         instead",
+                        composite_alpha, supported, chosen
+                    );
+                }
+                chosen
+            };
 
-            Swapchain::new(
+            let (swapchain, swapchain_images) = Swapchain::new(
                 device.clone(),
                 surface.clone(),
                 SwapchainCreateInfo {
                     min_image_count: surface_capabilities.min_image_count,
-                    image_format,
-                    image_extent: [window_config.width, window_config.height],
-                    image_usage: ImageUsage::COLOR_ATTACHMENT,
-                    composite_alpha: surface_capabilities
-                        .supported_composite_alpha
-                        .into_iter()
-                        .next()
-                        .ok_or(ApplicationCreationError::NoSupportedCompositeAlphasError)?,
+                    image_format: Some(image_format),
+                    image_color_space,
+                    image_extent: choose_image_extent([width, height], &surface_capabilities),
+                    image_usage,
+                    composite_alpha,
                     present_mode,
                     ..Default::default()
                 },
             )
-            .map_err(ApplicationCreationError::SwapchainCreationError)?
+            .map_err(ApplicationCreationError::SwapchainCreationError)?;
+
+            (swapchain, swapchain_images, output_strategy)
+        };
+
+        info!(
+            "Created swapchain: {}x{}, {:?}, {:?}, {} images",
+            swapchain.image_extent()[0],
+            swapchain.image_extent()[1],
+            swapchain.image_format(),
+            swapchain.present_mode(),
+            swapchain.image_count(),
+        );
+
+        let allocator = Allocator::new(device.clone());
+        let uniform_buffers = (0..MAX_FRAMES_IN_FLIGHT)
+            .map(|_| {
+                allocator.alloc_host_visible_buffer(
+                    BufferUsage::UNIFORM_BUFFER,
+                    UniformBufferObject::default(),
+                )
+            })
+            .collect::<Result<_, _>>()
+            .map_err(ApplicationCreationError::BufferError)?;
+        let command_buffer_allocator =
+            StandardCommandBufferAllocator::new(device.clone(), Default::default());
+
+        let output_image = match output_strategy {
+            OutputStrategy::Storage => {
+                info!("Output: writing straight to the swapchain images.");
+                None
+            }
+            OutputStrategy::Blit => {
+                info!("Output: writing to an offscreen image and blitting it to the swapchain.");
+                Some(
+                    alloc_output_image(&allocator, swapchain.image_extent(), &graphics_queue)
+                        .map_err(ApplicationCreationError::ImageError)?,
+                )
+            }
         };
 
         Ok(Application {
-            event_loop: el,
+            event_loop: Some(el),
             present_mode,
+            frame_pacer: FramePacer::new(fps_cap),
+            capture: None,
+            gpu_profiler: None,
+            input_recorder: None,
+            input_replay: None,
+            frame_index: 0,
+            quit_key: VirtualKeyCode::Escape,
+            input: Input::default(),
             window: Window {
                 config: window_config,
                 window: window.clone(),
@@ -259,39 +837,413 @@ impl Application {
             instance,
             surface,
             device,
+            graphics_queue,
+            compute_queue,
             swapchain,
-            uniform_buffers: Default::default(),
+            swapchain_images,
+            output_strategy,
+            render_resolution: RenderResolution::Window,
+            output_image,
+            clear_color: DEFAULT_CLEAR_COLOR,
+            uniform_buffer_object: Default::default(),
+            uniform_buffers,
             depth_buffer: Default::default(),
             graphics_pipeline: Default::default(),
             swapchain_frame_buffers: Default::default(),
-            command_pool: Default::default(),
+            allocator,
+            command_buffer_allocator,
             command_buffers: Default::default(),
             image_available_semaphores: Default::default(),
             render_finished_semaphores: Default::default(),
-            in_flight_fences: Default::default(),
-            current_frame: Default::default(),
+            in_flight_fences: vec![None; MAX_FRAMES_IN_FLIGHT],
+            current_frame: 0,
+            frame_timeout,
+            frame_timeouts: 0,
+            recreate_swapchain: false,
+            minimized: false,
+            idle: false,
+            close_requested: false,
         })
     }
 
-    pub fn run(self) {
-        self.event_loop
-            .run(move |event, _, control_flow| match event {
-                Event::WindowEvent {
-                    event: WindowEvent::CloseRequested,
-                    ..
-                } => {
+    pub fn supported_present_modes(&self) -> Vec<PresentMode> {
+        supported_present_modes(self.device.physical_device(), &self.surface)
+    }
+
+    /// The extent of the image the ray tracer renders into.
+    pub fn render_extent(&self) -> [u32; 2] {
+        self.render_resolution.extent(self.swapchain.image_extent())
+    }
+
+    /// Renders at `render_resolution` from now on. Anything but `RenderResolution::Window` needs
+    /// an offscreen image to scale from, so it switches to `OutputStrategy::Blit` if needed; if
+    /// the device can't blit into the swapchain images, it keeps rendering at the window size.
+    pub fn set_render_resolution(
+        &mut self,
+        render_resolution: RenderResolution,
+    ) -> Result<(), ImageError> {
+        if render_resolution != RenderResolution::Window
+            && self.output_strategy == OutputStrategy::Storage
+        {
+            let blit = supports_blit(self.device.physical_device(), self.swapchain.image_format());
+            if !matches!(blit, Ok(true)) {
+                warn!(
+                    "The device can't blit into the swapchain images; rendering at the window \
+                     size."
+                );
+                return Ok(());
+            }
+            info!("Output: switching to an offscreen image to render at a fixed resolution.");
+            self.output_strategy = OutputStrategy::Blit;
+        }
+        self.render_resolution = render_resolution;
+        if self.output_strategy == OutputStrategy::Blit {
+            self.output_image = Some(alloc_output_image(
+                &self.allocator,
+                self.render_extent(),
+                &self.graphics_queue,
+            )?);
+        }
+        let [width, height] = self.render_extent();
+        info!("Render resolution: {}x{}", width, height);
+        Ok(())
+    }
+
+    /// Exits the event loop once the current frame is done.
+    pub fn close(&mut self) {
+        self.close_requested = true;
+    }
+
+    /// Gives up on the frames still in flight. Dropping a fence waits for it without a timeout,
+    /// so exiting after the GPU stopped finishing frames would hang; the fences are leaked
+    /// instead, leaving their resources for the driver to reclaim when the process exits.
+    fn leak_in_flight_fences(&mut self) {
+        for fence in self.in_flight_fences.iter_mut().filter_map(Option::take) {
+            std::mem::forget(fence);
+        }
+    }
+
+    pub fn run(mut handler: impl ApplicationHandler + 'static) {
+        let event_loop = handler
+            .application()
+            .event_loop
+            .take()
+            .expect("Application::run called more than once");
+
+        event_loop.run(move |event, _, control_flow| match event {
+            Event::WindowEvent {
+                event: WindowEvent::CloseRequested,
+                ..
+            } => {
+                *control_flow = ControlFlow::Exit;
+            }
+            Event::WindowEvent {
+                event: WindowEvent::KeyboardInput { input, .. },
+                ..
+            } => {
+                let app = handler.application();
+                if let Some(recorder) = &mut app.input_recorder {
+                    recorder.record(app.frame_index, InputEvent::from_keyboard_input(&input));
+                }
+                let replaying = app.input_replay.is_some();
+                if !replaying || input.virtual_keycode == Some(app.quit_key) {
+                    handle_key(&mut handler, &input, control_flow);
+                }
+            }
+            Event::WindowEvent {
+                event: WindowEvent::Resized(size),
+                ..
+            } => {
+                debug!("Window resized to {}x{}", size.width, size.height);
+                let app = handler.application();
+                if let Some(recorder) = &mut app.input_recorder {
+                    let (width, height) = (size.width, size.height);
+                    recorder.record(app.frame_index, InputEvent::Resize { width, height });
+                }
+                let minimized = size.width == 0 || size.height == 0;
+                if minimized != app.minimized {
+                    app.minimized = minimized;
+                    if minimized {
+                        info!("Window minimized; pausing rendering.");
+                        // no redraws are needed until the window is restored.
+                        *control_flow = ControlFlow::Wait;
+                    } else {
+                        info!("Window restored; resuming rendering.");
+                        *control_flow = ControlFlow::Poll;
+                    }
+                }
+                app.recreate_swapchain = true;
+            }
+            // e.g. a borderless window moved onto a monitor with a different scale factor.
+            Event::WindowEvent {
+                event: WindowEvent::ScaleFactorChanged { .. },
+                ..
+            } => handler.application().recreate_swapchain = true,
+            Event::RedrawEventsCleared if handler.application().minimized => (),
+            Event::RedrawEventsCleared => {
+                handler.application().frame_pacer.wait();
+
+                loop {
+                    let app = handler.application();
+                    let frame_index = app.frame_index;
+                    let Some(event) = app
+                        .input_replay
+                        .as_mut()
+                        .and_then(|replay| replay.next_due(frame_index))
+                    else {
+                        break;
+                    };
+                    match event {
+                        InputEvent::Key { .. } => {
+                            let input = event.keyboard_input().expect("event is a key event");
+                            handle_key(&mut handler, &input, control_flow);
+                        }
+                        // the Resized event that follows recreates the swapchain.
+                        InputEvent::Resize { width, height } => app
+                            .window
+                            .window
+                            .set_inner_size(PhysicalSize::new(width, height)),
+                    }
+                }
+
+                let keys: Vec<_> = handler.application().input.just_pressed_keys().collect();
+                for key in keys {
+                    handler.on_key(key);
+                }
+                handler.on_frame();
+                let app = handler.application();
+                app.input.end_frame();
+                app.frame_index += 1;
+                if app.close_requested {
                     *control_flow = ControlFlow::Exit;
+                    return;
+                }
+                *control_flow = match app.idle {
+                    true => ControlFlow::WaitUntil(Instant::now() + IDLE_REDRAW_PERIOD),
+                    false => ControlFlow::Poll,
+                };
+                if let Some(capture) = &mut app.capture {
+                    capture.on_frame();
                 }
-                Event::WindowEvent {
-                    event: WindowEvent::KeyboardInput { input, .. },
-                    ..
-                } => {
-                    if let Some(VirtualKeyCode::Escape) = input.virtual_keycode {
+
+                match handler.application().draw_frame() {
+                    Ok(()) => (),
+                    Err(e) if !e.is_recoverable() => {
+                        error!("Failed to draw frame:\n\t{}", e);
+                        if let DrawError::FrameTimeout(_) = e {
+                            handler.application().leak_in_flight_fences();
+                        }
                         *control_flow = ControlFlow::Exit;
                     }
+                    Err(e @ DrawError::FrameTimeout(_)) => {
+                        warn!("{}", e);
+                        handler.on_frame_timeout();
+                    }
+                    Err(e) => {
+                        warn!("Failed to draw frame, recreating swapchain: {}", e);
+                        handler.application().recreate_swapchain = true;
+                    }
                 }
-                _ => (),
+            }
+            Event::LoopDestroyed => handler.on_exit(),
+            _ => (),
+        });
+    }
+
+    fn draw_frame(&mut self) -> Result<(), DrawError> {
+        // mostly taken from vulkano examples.
+
+        // wait for the last frame that used this slot, but don't hang forever on a stuck GPU.
+        if let Some(fence) = &self.in_flight_fences[self.current_frame] {
+            match fence.wait(Some(self.frame_timeout)) {
+                Ok(()) => self.frame_timeouts = 0,
+                Err(FlushError::Timeout) => {
+                    self.frame_timeouts += 1;
+                    return Err(DrawError::FrameTimeout(self.frame_timeouts));
+                }
+                Err(e) => return Err(DrawError::FlushError(e)),
+            }
+        }
+
+        *self.uniform_buffers[self.current_frame]
+            .write()
+            .map_err(DrawError::BufferError)? = self.uniform_buffer_object;
+
+        if self.recreate_swapchain {
+            self.recreate_swapchain()?;
+        }
+
+        let (image_index, suboptimal, acquire_future) =
+            match acquire_next_image(self.swapchain.clone(), None) {
+                Ok(r) => r,
+                Err(AcquireError::OutOfDate) => {
+                    self.recreate_swapchain = true;
+                    return Ok(());
+                }
+                Err(e) => return Err(DrawError::AcquireError(e)),
+            };
+
+        // A suboptimal swapchain can still be presented to, but on some drivers it
+        // slowly drifts out of sync with the surface (e.g. stretched output after a
+        // resize), so treat it the same as `OutOfDate` starting with the next frame.
+        // vulkano's `PresentFuture` only reports `OutOfDate` at present time and
+        // swallows the suboptimal flag, so acquire is where it gets caught.
+        if suboptimal {
+            info!("Swapchain is suboptimal; recreating it on the next frame.");
+            self.recreate_swapchain = true;
+        }
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            &self.command_buffer_allocator,
+            self.graphics_queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .map_err(DrawError::CommandBufferBeginError)?;
+
+        if let Some(profiler) = &mut self.gpu_profiler {
+            profiler.begin_frame(&mut builder, self.current_frame);
+        }
+
+        let swapchain_image = self.swapchain_images[image_index as usize].clone();
+
+        // With a transparent window, the background is left see-through.
+        let alpha = match self.swapchain.composite_alpha() {
+            CompositeAlpha::Opaque => 1.0,
+            _ => 0.0,
+        };
+        let [r, g, b] = self.clear_color.map(|c| c * alpha);
+        let clear_value = ClearColorValue::Float([r, g, b, alpha]);
+
+        // the swapchain image is cleared before anything else, so it never shows stale memory.
+        // Until the ray tracing pipeline is ported, clearing the offscreen image also stands in
+        // for its output.
+        self.begin_label(&mut builder, "Clear");
+        builder
+            .clear_color_image(ClearColorImageInfo {
+                clear_value,
+                ..ClearColorImageInfo::image(swapchain_image.clone())
+            })
+            .map_err(DrawError::ClearError)?;
+        if let Some(output_image) = self.output_image.clone() {
+            builder
+                .clear_color_image(ClearColorImageInfo {
+                    clear_value,
+                    ..ClearColorImageInfo::image(output_image)
+                })
+                .map_err(DrawError::ClearError)?;
+        }
+        self.end_label(&mut builder);
+
+        if let Some(output_image) = self.output_image.clone() {
+            self.begin_label(&mut builder, "Blit");
+            builder
+                .blit_image(BlitImageInfo {
+                    filter: Filter::Linear,
+                    ..BlitImageInfo::images(output_image, swapchain_image)
+                })
+                .map_err(DrawError::CopyError)?;
+            self.end_label(&mut builder);
+        }
+
+        let command_buffer = builder.build().map_err(DrawError::BuildError)?;
+
+        let previous_frame = (self.current_frame + MAX_FRAMES_IN_FLIGHT - 1) % MAX_FRAMES_IN_FLIGHT;
+        let previous_future = match self.in_flight_fences[previous_frame].clone() {
+            Some(fence) => fence.boxed(),
+            None => sync::now(self.device.clone()).boxed(),
+        };
+
+        let future = previous_future
+            .join(acquire_future)
+            .then_execute(self.graphics_queue.clone(), command_buffer)
+            .map_err(DrawError::CommandBufferExecError)?
+            .then_swapchain_present(
+                self.graphics_queue.clone(),
+                SwapchainPresentInfo::swapchain_image_index(self.swapchain.clone(), image_index),
+            )
+            .then_signal_fence_and_flush();
+
+        self.in_flight_fences[self.current_frame] = match future {
+            Ok(future) => Some(Arc::new(future)),
+            Err(FlushError::OutOfDate) => {
+                self.recreate_swapchain = true;
+                None
+            }
+            Err(e) => return Err(DrawError::FlushError(e)),
+        };
+        self.current_frame = (self.current_frame + 1) % MAX_FRAMES_IN_FLIGHT;
+
+        Ok(())
+    }
+
+    /// Starts a named region of commands that shows up in debuggers, and is timed when GPU
+    /// profiling. The label does nothing without the debug utils extension.
+    fn begin_label<L>(&mut self, builder: &mut AutoCommandBufferBuilder<L>, name: &str) {
+        if let Some(profiler) = &mut self.gpu_profiler {
+            profiler.begin_pass(builder, name);
+        }
+        if self.instance.enabled_extensions().ext_debug_utils {
+            // labels are purely informative, so failing to add one isn't worth failing the frame.
+            let _ = builder.begin_debug_utils_label(DebugUtilsLabel {
+                label_name: name.into(),
+                ..Default::default()
             });
+        }
+    }
+
+    fn end_label<L>(&mut self, builder: &mut AutoCommandBufferBuilder<L>) {
+        if self.instance.enabled_extensions().ext_debug_utils {
+            // safety: only called after a matching `begin_label`.
+            let _ = unsafe { builder.end_debug_utils_label() };
+        }
+        if let Some(profiler) = &mut self.gpu_profiler {
+            profiler.end_pass(builder);
+        }
+    }
+
+    fn recreate_swapchain(&mut self) -> Result<(), DrawError> {
+        let surface_capabilities = self
+            .device
+            .physical_device()
+            .surface_capabilities(&self.surface, Default::default())
+            .map_err(DrawError::PhysicalDeviceError)?;
+        let image_extent = choose_image_extent(
+            self.window.window.inner_size().into(),
+            &surface_capabilities,
+        );
+
+        let (swapchain, swapchain_images) = match self.swapchain.recreate(SwapchainCreateInfo {
+            image_extent,
+            ..self.swapchain.create_info()
+        }) {
+            Ok(r) => r,
+            // the window was resized again since the event was received; try next frame.
+            Err(SwapchainCreationError::ImageExtentNotSupported { .. }) => return Ok(()),
+            Err(e) => return Err(DrawError::SwapchainCreationError(e)),
+        };
+
+        debug!(
+            "Recreated swapchain: {}x{}",
+            swapchain.image_extent()[0],
+            swapchain.image_extent()[1]
+        );
+
+        // a fixed render resolution keeps its image across resizes.
+        if let Some(output_image) = &self.output_image {
+            let render_extent = self.render_resolution.extent(swapchain.image_extent());
+            if output_image.dimensions().width_height() != render_extent {
+                self.output_image = Some(
+                    alloc_output_image(&self.allocator, render_extent, &self.graphics_queue)
+                        .map_err(DrawError::ImageError)?,
+                );
+            }
+        }
+
+        self.swapchain = swapchain;
+        self.swapchain_images = swapchain_images;
+        self.recreate_swapchain = false;
+
+        Ok(())
     }
 }
 
@@ -301,7 +1253,11 @@ pub enum ApplicationCreationError {
     NoPrimaryMonitorError,
     NoVideoModeError,
     NoPhysicalDevicesError,
+    NoVisibleDevicesError(Vec<u32>),
     NoSupportedCompositeAlphasError,
+    NoSupportedSurfaceFormatsError,
+    NoSupportedImageUsageError,
+    NoSupportedOutputStrategyError,
     NoGraphicsQueueError,
     NoComputeQueueError,
     LoadingError(LoadingError),
@@ -312,22 +1268,41 @@ pub enum ApplicationCreationError {
     DeviceCreationError(DeviceCreationError),
     PhysicalDeviceError(PhysicalDeviceError),
     SwapchainCreationError(SwapchainCreationError),
+    BufferError(BufferError),
+    ImageError(ImageError),
 }
 impl std::fmt::Display for ApplicationCreationError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             ApplicationCreationError::NoPrimaryMonitorError => {
-                write!(f, "{:?}: Could not find a primary monitor.", self)
+                write!(
+                    f,
+                    "{:?}: Could not find the --monitor or a primary monitor to go fullscreen on.",
+                    self
+                )
             }
             ApplicationCreationError::NoVideoModeError => {
                 write!(
                     f,
-                    "{:?}: Could not find a fullscreen video mode for the primary monitor.",
+                    "{:?}: Could not find a fullscreen video mode of the requested size for the \
+                     chosen monitor (--monitor, or the primary one). Try --borderless instead.",
                     self
                 )
             }
             ApplicationCreationError::NoPhysicalDevicesError => {
-                write!(f, "{:?}: Could not find a physical device.", self)
+                write!(
+                    f,
+                    "{:?}: Could not find a physical device that supports ray tracing.",
+                    self
+                )
+            }
+            ApplicationCreationError::NoVisibleDevicesError(available) => {
+                write!(
+                    f,
+                    "NoVisibleDevicesError: None of the --visible-device IDs match a physical \
+                     device. Available device IDs: {:?}.",
+                    available
+                )
             }
             ApplicationCreationError::NoSupportedCompositeAlphasError => {
                 write!(
@@ -336,6 +1311,28 @@ impl std::fmt::Display for ApplicationCreationError {
                     self
                 )
             }
+            ApplicationCreationError::NoSupportedSurfaceFormatsError => {
+                write!(
+                    f,
+                    "{:?}: Could not find any supported formats for the surface.",
+                    self
+                )
+            }
+            ApplicationCreationError::NoSupportedImageUsageError => {
+                write!(
+                    f,
+                    "{:?}: The surface doesn't support transfers into swapchain images.",
+                    self
+                )
+            }
+            ApplicationCreationError::NoSupportedOutputStrategyError => {
+                write!(
+                    f,
+                    "{:?}: The swapchain format supports neither storage images nor blits from \
+                     an offscreen image.",
+                    self
+                )
+            }
             ApplicationCreationError::NoGraphicsQueueError => {
                 write!(f, "{:?}: Could not create a drawable graphics queue.", self)
             }
@@ -350,7 +1347,211 @@ impl std::fmt::Display for ApplicationCreationError {
             ApplicationCreationError::DeviceCreationError(e) => std::fmt::Display::fmt(e, f),
             ApplicationCreationError::PhysicalDeviceError(e) => std::fmt::Display::fmt(e, f),
             ApplicationCreationError::SwapchainCreationError(e) => std::fmt::Display::fmt(e, f),
+            ApplicationCreationError::BufferError(e) => std::fmt::Display::fmt(e, f),
+            ApplicationCreationError::ImageError(e) => std::fmt::Display::fmt(e, f),
         }
     }
 }
 impl std::error::Error for ApplicationCreationError {}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum DrawError {
+    AcquireError(AcquireError),
+    CommandBufferBeginError(CommandBufferBeginError),
+    ClearError(ClearError),
+    BuildError(BuildError),
+    CommandBufferExecError(CommandBufferExecError),
+    FlushError(FlushError),
+    SwapchainCreationError(SwapchainCreationError),
+    BufferError(BufferError),
+    ImageError(ImageError),
+    CopyError(CopyError),
+    PhysicalDeviceError(PhysicalDeviceError),
+    /// The number of frames in a row that weren't finished within the frame timeout.
+    FrameTimeout(u32),
+}
+impl DrawError {
+    /// Whether the frame loop can carry on after this error by recreating the swapchain. Only
+    /// errors that a new swapchain fixes count, and frame timeouts until `MAX_FRAME_TIMEOUTS`;
+    /// anything else would most likely fail again every frame.
+    pub fn is_recoverable(&self) -> bool {
+        match self {
+            DrawError::AcquireError(
+                AcquireError::OutOfDate | AcquireError::FullScreenExclusiveModeLost,
+            )
+            | DrawError::FlushError(
+                FlushError::OutOfDate | FlushError::FullScreenExclusiveModeLost,
+            )
+            | DrawError::SwapchainCreationError(
+                SwapchainCreationError::ImageExtentNotSupported { .. },
+            ) => true,
+            DrawError::FrameTimeout(n) => *n < MAX_FRAME_TIMEOUTS,
+            _ => false,
+        }
+    }
+}
+impl std::fmt::Display for DrawError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DrawError::AcquireError(e) => std::fmt::Display::fmt(e, f),
+            DrawError::CommandBufferBeginError(e) => std::fmt::Display::fmt(e, f),
+            DrawError::ClearError(e) => std::fmt::Display::fmt(e, f),
+            DrawError::BuildError(e) => std::fmt::Display::fmt(e, f),
+            DrawError::CommandBufferExecError(e) => std::fmt::Display::fmt(e, f),
+            DrawError::FlushError(e) => std::fmt::Display::fmt(e, f),
+            DrawError::SwapchainCreationError(e) => std::fmt::Display::fmt(e, f),
+            DrawError::BufferError(e) => std::fmt::Display::fmt(e, f),
+            DrawError::ImageError(e) => std::fmt::Display::fmt(e, f),
+            DrawError::CopyError(e) => std::fmt::Display::fmt(e, f),
+            DrawError::PhysicalDeviceError(e) => std::fmt::Display::fmt(e, f),
+            DrawError::FrameTimeout(n) => write!(
+                f,
+                "FrameTimeout: The GPU did not finish a frame within the frame timeout ({} of {} \
+                 in a row).",
+                n, MAX_FRAME_TIMEOUTS
+            ),
+        }
+    }
+}
+impl std::error::Error for DrawError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(device_id: u32, device_type: PhysicalDeviceType) -> DeviceCandidate {
+        DeviceCandidate {
+            device_id,
+            device_type,
+            has_required_extensions: true,
+            has_swapchain: true,
+            max_geometry_count: Some(1 << 24),
+        }
+    }
+
+    #[test]
+    fn prefers_discrete_over_integrated() {
+        let candidates = [
+            candidate(0, PhysicalDeviceType::IntegratedGpu),
+            candidate(1, PhysicalDeviceType::Cpu),
+            candidate(2, PhysicalDeviceType::DiscreteGpu),
+        ];
+        assert_eq!(choose_device(&candidates, &None), Some(2));
+    }
+
+    #[test]
+    fn skips_devices_without_ray_tracing() {
+        let candidates = [
+            DeviceCandidate {
+                has_required_extensions: false,
+                ..candidate(0, PhysicalDeviceType::DiscreteGpu)
+            },
+            DeviceCandidate {
+                max_geometry_count: None,
+                ..candidate(1, PhysicalDeviceType::DiscreteGpu)
+            },
+            candidate(2, PhysicalDeviceType::IntegratedGpu),
+        ];
+        assert_eq!(choose_device(&candidates, &None), Some(2));
+    }
+
+    #[test]
+    fn visible_devices_compose_with_extension_filter() {
+        let candidates = [
+            candidate(0, PhysicalDeviceType::DiscreteGpu),
+            candidate(1, PhysicalDeviceType::IntegratedGpu),
+            DeviceCandidate {
+                has_required_extensions: false,
+                ..candidate(2, PhysicalDeviceType::DiscreteGpu)
+            },
+        ];
+        assert_eq!(choose_device(&candidates, &Some(vec![1, 2])), Some(1));
+        assert_eq!(choose_device(&candidates, &Some(vec![2])), None);
+    }
+
+    #[test]
+    fn falls_back_to_a_visible_software_device() {
+        let candidates = [
+            DeviceCandidate {
+                has_required_extensions: false,
+                ..candidate(0, PhysicalDeviceType::IntegratedGpu)
+            },
+            DeviceCandidate {
+                has_required_extensions: false,
+                ..candidate(1, PhysicalDeviceType::Cpu)
+            },
+        ];
+        assert_eq!(choose_device(&candidates, &None), None);
+        // the GPU lacks ray tracing; that's an error, not a reason to run on the CPU.
+        assert_eq!(choose_software_device(&candidates, &None), None);
+        assert_eq!(choose_software_device(&candidates, &Some(vec![0])), None);
+        assert_eq!(choose_software_device(&candidates, &Some(vec![1])), Some(1));
+        assert_eq!(choose_software_device(&candidates[1..], &None), Some(0));
+    }
+
+    #[test]
+    fn surface_format_prefers_bgra_srgb() {
+        let formats = [
+            (Format::B8G8R8A8_UNORM, ColorSpace::SrgbNonLinear),
+            (Format::R8G8B8A8_SRGB, ColorSpace::SrgbNonLinear),
+            (Format::B8G8R8A8_SRGB, ColorSpace::SrgbNonLinear),
+        ];
+        assert_eq!(choose_surface_format(&formats), Some(formats[2]));
+        assert_eq!(choose_surface_format(&formats[..2]), Some(formats[1]));
+    }
+
+    #[test]
+    fn surface_format_falls_back_to_first() {
+        let formats = [
+            (Format::A2B10G10R10_UNORM_PACK32, ColorSpace::SrgbNonLinear),
+            (Format::B8G8R8A8_SRGB, ColorSpace::ExtendedSrgbLinear),
+        ];
+        assert_eq!(choose_surface_format(&formats), Some(formats[0]));
+        assert_eq!(choose_surface_format(&[]), None);
+    }
+
+    #[test]
+    fn present_mode_falls_back_to_fifo() {
+        let supported = [PresentMode::Fifo, PresentMode::Mailbox];
+        assert_eq!(
+            choose_present_mode(PresentMode::Mailbox, &supported),
+            PresentMode::Mailbox
+        );
+        assert_eq!(
+            choose_present_mode(PresentMode::Immediate, &supported),
+            PresentMode::Mailbox
+        );
+        assert_eq!(
+            choose_present_mode(PresentMode::FifoRelaxed, &supported),
+            PresentMode::Fifo
+        );
+        assert_eq!(
+            choose_present_mode(PresentMode::Immediate, &[PresentMode::Fifo]),
+            PresentMode::Fifo
+        );
+    }
+
+    #[test]
+    fn composite_alpha_falls_back_to_opaque() {
+        let supported = [CompositeAlpha::Inherit, CompositeAlpha::Opaque];
+        assert_eq!(
+            choose_composite_alpha(CompositeAlpha::PreMultiplied, &supported),
+            Some(CompositeAlpha::Opaque)
+        );
+        assert_eq!(
+            choose_composite_alpha(CompositeAlpha::PreMultiplied, &[CompositeAlpha::Inherit]),
+            Some(CompositeAlpha::Inherit)
+        );
+        assert_eq!(choose_composite_alpha(CompositeAlpha::Opaque, &[]), None);
+    }
+
+    #[test]
+    fn only_swapchain_errors_and_early_timeouts_are_recoverable() {
+        assert!(DrawError::AcquireError(AcquireError::OutOfDate).is_recoverable());
+        assert!(DrawError::FrameTimeout(MAX_FRAME_TIMEOUTS - 1).is_recoverable());
+        assert!(!DrawError::FrameTimeout(MAX_FRAME_TIMEOUTS).is_recoverable());
+        assert!(!DrawError::FlushError(FlushError::DeviceLost).is_recoverable());
+        assert!(!DrawError::AcquireError(AcquireError::Timeout).is_recoverable());
+    }
+}
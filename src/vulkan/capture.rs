@@ -0,0 +1,37 @@
+use log::{debug, info};
+use renderdoc::{RenderDoc, V110};
+
+/// Captures a single frame with RenderDoc, when the application was launched from it.
+pub struct FrameCapture {
+    renderdoc: RenderDoc<V110>,
+    frames_left: Option<u32>,
+}
+
+impl FrameCapture {
+    /// Captures frame number `frame`, or returns `None` if the RenderDoc API isn't available.
+    pub fn new(frame: u32) -> Option<FrameCapture> {
+        match RenderDoc::new() {
+            Ok(renderdoc) => Some(FrameCapture {
+                renderdoc,
+                frames_left: Some(frame),
+            }),
+            Err(e) => {
+                debug!("RenderDoc is not available, not capturing: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Called once per frame, before it is drawn.
+    pub fn on_frame(&mut self) {
+        match self.frames_left {
+            Some(0) => {
+                self.renderdoc.trigger_capture();
+                info!("Triggered a RenderDoc capture.");
+                self.frames_left = None;
+            }
+            Some(n) => self.frames_left = Some(n - 1),
+            None => (),
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use serde::Serialize;
+use vulkano::{device::physical::PhysicalDevice, memory::MemoryHeapFlags};
+
+/// A snapshot of the properties of a [`PhysicalDevice`] that are worth reporting, in a form that
+/// can be printed for people or serialized for tooling.
+#[derive(Serialize)]
+pub struct DeviceInfo {
+    pub device_id: u32,
+    pub name: String,
+    pub device_type: String,
+    pub vendor: String,
+    pub vulkan_version: String,
+    pub driver_name: Option<String>,
+    pub driver_info: Option<String>,
+    pub driver_version: u32,
+    pub ray_tracing: RayTracingSupport,
+    pub memory_heaps: Vec<MemoryHeapInfo>,
+    /// The present modes supported for a probe surface, if one could be created.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub present_modes: Option<Vec<String>>,
+}
+
+#[derive(Serialize)]
+pub struct RayTracingSupport {
+    pub ray_tracing_pipeline: bool,
+    pub acceleration_structure: bool,
+    pub deferred_host_operations: bool,
+    pub shader_clock: bool,
+}
+
+#[derive(Serialize)]
+pub struct MemoryHeapInfo {
+    pub size: u64,
+    pub device_local: bool,
+}
+
+impl From<&PhysicalDevice> for DeviceInfo {
+    fn from(pd: &PhysicalDevice) -> Self {
+        let props = pd.properties();
+        let extensions = pd.supported_extensions();
+
+        DeviceInfo {
+            device_id: props.device_id,
+            name: props.device_name.clone(),
+            device_type: format!("{:?}", props.device_type),
+            vendor: vendor_name(props.vendor_id).into(),
+            vulkan_version: props.api_version.to_string(),
+            driver_name: props.driver_name.clone(),
+            driver_info: props.driver_info.clone(),
+            driver_version: props.driver_version,
+            ray_tracing: RayTracingSupport {
+                ray_tracing_pipeline: extensions.khr_ray_tracing_pipeline,
+                acceleration_structure: extensions.khr_acceleration_structure,
+                deferred_host_operations: extensions.khr_deferred_host_operations,
+                shader_clock: extensions.khr_shader_clock,
+            },
+            memory_heaps: pd
+                .memory_properties()
+                .memory_heaps
+                .iter()
+                .map(|heap| MemoryHeapInfo {
+                    size: heap.size,
+                    device_local: heap.flags.intersects(MemoryHeapFlags::DEVICE_LOCAL),
+                })
+                .collect(),
+            present_modes: None,
+        }
+    }
+}
+
+pub fn vendor_name(vendor_id: u32) -> &'static str {
+    match vendor_id {
+        0x1002 => "AMD",
+        0x1010 => "ImgTec",
+        0x10DE => "NVIDIA",
+        0x13B5 => "ARM",
+        0x5143 => "Qualcomm",
+        0x8086 => "INTEL",
+        _ => "UnknownVendor",
+    }
+}
@@ -0,0 +1,52 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+/// `thread::sleep` routinely overshoots by a millisecond or so, so the last stretch before a
+/// deadline is spent spinning instead.
+const SPIN_THRESHOLD: Duration = Duration::from_millis(2);
+
+/// Limits the frame rate to a fixed cap by blocking the CPU between frames.
+///
+/// Frames are paced against a running deadline rather than a fixed sleep, so time already spent
+/// rendering and presenting counts towards the frame budget.
+pub struct FramePacer {
+    frame_time: Option<Duration>,
+    next_frame: Instant,
+}
+
+impl FramePacer {
+    /// Creates a pacer targeting `fps_cap` frames per second. A cap of 0 means uncapped.
+    pub fn new(fps_cap: u32) -> FramePacer {
+        FramePacer {
+            frame_time: (fps_cap > 0).then(|| Duration::from_secs(1) / fps_cap),
+            next_frame: Instant::now(),
+        }
+    }
+
+    /// Blocks until the next frame is due.
+    pub fn wait(&mut self) {
+        let Some(frame_time) = self.frame_time else {
+            return;
+        };
+
+        let now = Instant::now();
+        if now >= self.next_frame {
+            // the previous frame ran over budget; start a fresh cadence instead of bursting
+            // frames to catch up.
+            self.next_frame = now + frame_time;
+            return;
+        }
+
+        let remaining = self.next_frame - now;
+        if remaining > SPIN_THRESHOLD {
+            thread::sleep(remaining - SPIN_THRESHOLD);
+        }
+        while Instant::now() < self.next_frame {
+            std::hint::spin_loop();
+        }
+
+        self.next_frame += frame_time;
+    }
+}
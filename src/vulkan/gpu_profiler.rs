@@ -0,0 +1,140 @@
+use std::sync::Arc;
+
+use log::warn;
+use vulkano::{
+    command_buffer::AutoCommandBufferBuilder,
+    device::Queue,
+    query::{QueryPool, QueryPoolCreateInfo, QueryResultFlags, QueryType},
+    sync::PipelineStage,
+};
+
+use super::application::MAX_FRAMES_IN_FLIGHT;
+
+/// The most passes timed per frame; any further passes go untimed.
+const MAX_PASSES: u32 = 16;
+
+/// Times the labeled passes of each frame on the GPU with timestamp queries.
+///
+/// Each frame in flight has its own query pool, which is read back once its slot comes around
+/// again, so the timings lag `MAX_FRAMES_IN_FLIGHT` frames behind but never stall the GPU.
+pub struct GpuProfiler {
+    query_pools: Vec<Arc<QueryPool>>,
+    /// The passes written to each query pool so far, with a start and end query each.
+    passes: Vec<Vec<String>>,
+    /// Whether the pass started last was timed and hasn't ended yet; untimed passes (over
+    /// `MAX_PASSES`) have no start query, so their end isn't written either.
+    pass_open: bool,
+    current_frame: usize,
+    /// Nanoseconds per timestamp tick.
+    timestamp_period: f32,
+    /// How long each pass of the latest finished frame took, in milliseconds.
+    pub pass_times: Vec<(String, f32)>,
+}
+
+impl GpuProfiler {
+    /// Returns `None`, with a warning, if `queue` doesn't support timestamps.
+    pub fn new(queue: &Queue) -> Option<GpuProfiler> {
+        let physical_device = queue.device().physical_device();
+        let family =
+            &physical_device.queue_family_properties()[queue.queue_family_index() as usize];
+        if family.timestamp_valid_bits.is_none() {
+            warn!("The graphics queue doesn't support timestamps; GPU profiling is disabled.");
+            return None;
+        }
+
+        let query_pools = (0..MAX_FRAMES_IN_FLIGHT)
+            .map(|_| {
+                QueryPool::new(
+                    queue.device().clone(),
+                    QueryPoolCreateInfo {
+                        query_count: MAX_PASSES * 2,
+                        ..QueryPoolCreateInfo::query_type(QueryType::Timestamp)
+                    },
+                )
+            })
+            .collect::<Result<Vec<_>, _>>();
+        let query_pools = match query_pools {
+            Ok(query_pools) => query_pools,
+            Err(e) => {
+                warn!("Failed to create timestamp query pools: {}", e);
+                return None;
+            }
+        };
+
+        Some(GpuProfiler {
+            query_pools,
+            passes: vec![Vec::new(); MAX_FRAMES_IN_FLIGHT],
+            pass_open: false,
+            current_frame: 0,
+            timestamp_period: physical_device.properties().timestamp_period,
+            pass_times: Vec::new(),
+        })
+    }
+
+    /// Reads back the timings last written for `frame` and starts timing it anew. Must be the
+    /// first command of the frame, after waiting for the frame's fence.
+    pub fn begin_frame<L>(&mut self, builder: &mut AutoCommandBufferBuilder<L>, frame: usize) {
+        self.current_frame = frame;
+        self.read_back();
+
+        let query_pool = self.query_pools[frame].clone();
+        // safety: the fence of this frame has been waited on, so the GPU is done with its queries.
+        let _ = unsafe { builder.reset_query_pool(query_pool, 0..MAX_PASSES * 2) };
+        self.passes[frame].clear();
+        self.pass_open = false;
+    }
+
+    pub fn begin_pass<L>(&mut self, builder: &mut AutoCommandBufferBuilder<L>, name: &str) {
+        let passes = &mut self.passes[self.current_frame];
+        if passes.len() as u32 >= MAX_PASSES {
+            return;
+        }
+        let query = passes.len() as u32 * 2;
+        passes.push(name.into());
+        self.pass_open = true;
+
+        let query_pool = self.query_pools[self.current_frame].clone();
+        // safety: the query was reset at the start of the frame and is written only once.
+        let _ = unsafe { builder.write_timestamp(query_pool, query, PipelineStage::TopOfPipe) };
+    }
+
+    /// Ends the pass started last.
+    pub fn end_pass<L>(&mut self, builder: &mut AutoCommandBufferBuilder<L>) {
+        if !std::mem::take(&mut self.pass_open) {
+            return;
+        }
+        let query = self.passes[self.current_frame].len() as u32 * 2 - 1;
+
+        let query_pool = self.query_pools[self.current_frame].clone();
+        // safety: as in `begin_pass`.
+        let _ = unsafe { builder.write_timestamp(query_pool, query, PipelineStage::BottomOfPipe) };
+    }
+
+    fn read_back(&mut self) {
+        let passes = &self.passes[self.current_frame];
+        if passes.is_empty() {
+            return;
+        }
+
+        let mut timestamps = vec![0u64; passes.len() * 2];
+        let available = self.query_pools[self.current_frame]
+            .queries_range(0..timestamps.len() as u32)
+            .map(|range| range.get_results(&mut timestamps, QueryResultFlags::empty()));
+        // a frame that was never submitted leaves its queries unavailable; keep the last timings.
+        if !matches!(available, Some(Ok(true))) {
+            return;
+        }
+
+        self.pass_times = passes
+            .iter()
+            .zip(timestamps.chunks_exact(2))
+            .map(|(name, t)| {
+                let ticks = t[1].wrapping_sub(t[0]);
+                (
+                    name.clone(),
+                    ticks as f32 * self.timestamp_period / 1_000_000.0,
+                )
+            })
+            .collect();
+    }
+}
@@ -0,0 +1,104 @@
+use std::collections::HashSet;
+
+use winit::event::{ElementState, KeyboardInput, VirtualKeyCode};
+
+/// The keyboard state, updated from every key event. winit repeats `Pressed` while a key is
+/// held, so one-shot actions should check [`Input::just_pressed`] and continuous ones (e.g.
+/// camera movement) [`Input::is_held`]. The event loop passes the former to
+/// `ApplicationHandler::on_key`.
+#[derive(Default)]
+pub struct Input {
+    held: HashSet<VirtualKeyCode>,
+    // in arrival order, so that actions are applied (and replayed) deterministically. `held`
+    // keeps repeats out; a key pressed, released and pressed again within a frame is listed twice.
+    just_pressed: Vec<VirtualKeyCode>,
+    just_released: Vec<VirtualKeyCode>,
+}
+
+impl Input {
+    pub fn update(&mut self, input: &KeyboardInput) {
+        let Some(key) = input.virtual_keycode else {
+            return;
+        };
+
+        // repeats of a held key don't count as presses.
+        match input.state {
+            ElementState::Pressed => {
+                if self.held.insert(key) {
+                    self.just_pressed.push(key);
+                }
+            }
+            ElementState::Released => {
+                if self.held.remove(&key) {
+                    self.just_released.push(key);
+                }
+            }
+        }
+    }
+
+    /// Pressed since the last frame, as opposed to held down from before.
+    pub fn just_pressed(&self, key: VirtualKeyCode) -> bool {
+        self.just_pressed.contains(&key)
+    }
+
+    /// The keys pressed since the last frame, in the order they were pressed.
+    pub fn just_pressed_keys(&self) -> impl Iterator<Item = VirtualKeyCode> + '_ {
+        self.just_pressed.iter().copied()
+    }
+
+    pub fn just_released(&self, key: VirtualKeyCode) -> bool {
+        self.just_released.contains(&key)
+    }
+
+    pub fn is_held(&self, key: VirtualKeyCode) -> bool {
+        self.held.contains(&key)
+    }
+
+    /// Called once a frame has been handled, so that presses and releases count only once.
+    pub fn end_frame(&mut self) {
+        self.just_pressed.clear();
+        self.just_released.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vulkan::input_log::InputEvent;
+
+    fn key_event(key: VirtualKeyCode, pressed: bool) -> KeyboardInput {
+        InputEvent::Key {
+            pressed,
+            key: Some(key),
+            scancode: 0,
+        }
+        .keyboard_input()
+        .expect("event is a key event")
+    }
+
+    #[test]
+    fn repeats_are_not_new_presses() {
+        let mut input = Input::default();
+        input.update(&key_event(VirtualKeyCode::H, true));
+        assert!(input.just_pressed(VirtualKeyCode::H));
+
+        input.end_frame();
+        input.update(&key_event(VirtualKeyCode::H, true));
+        assert!(!input.just_pressed(VirtualKeyCode::H));
+        assert!(input.is_held(VirtualKeyCode::H));
+
+        input.update(&key_event(VirtualKeyCode::H, false));
+        assert!(input.just_released(VirtualKeyCode::H));
+        assert!(!input.is_held(VirtualKeyCode::H));
+    }
+    #[test]
+    fn presses_keep_their_order() {
+        let mut input = Input::default();
+        input.update(&key_event(VirtualKeyCode::L, true));
+        input.update(&key_event(VirtualKeyCode::H, true));
+        input.update(&key_event(VirtualKeyCode::L, true));
+
+        let keys: Vec<_> = input.just_pressed_keys().collect();
+        assert_eq!(keys, [VirtualKeyCode::L, VirtualKeyCode::H]);
+    }
+}
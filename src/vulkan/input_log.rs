@@ -0,0 +1,124 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+use winit::event::{ElementState, KeyboardInput, ModifiersState, VirtualKeyCode};
+
+/// An input event the application reacts to, as stored in an input log.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum InputEvent {
+    Key {
+        pressed: bool,
+        key: Option<VirtualKeyCode>,
+        scancode: u32,
+    },
+    Resize {
+        width: u32,
+        height: u32,
+    },
+}
+
+/// One line of an input log: an event and the frame it happened before.
+#[derive(Serialize, Deserialize)]
+struct Record {
+    frame: u64,
+    #[serde(flatten)]
+    event: InputEvent,
+}
+
+impl InputEvent {
+    pub fn from_keyboard_input(input: &KeyboardInput) -> InputEvent {
+        InputEvent::Key {
+            pressed: input.state == ElementState::Pressed,
+            key: input.virtual_keycode,
+            scancode: input.scancode,
+        }
+    }
+
+    /// The keyboard input of a `Key` event.
+    #[allow(deprecated)] // `KeyboardInput::modifiers` has to be filled in regardless.
+    pub fn keyboard_input(&self) -> Option<KeyboardInput> {
+        match *self {
+            InputEvent::Key {
+                pressed,
+                key,
+                scancode,
+            } => Some(KeyboardInput {
+                scancode,
+                state: match pressed {
+                    true => ElementState::Pressed,
+                    false => ElementState::Released,
+                },
+                virtual_keycode: key,
+                modifiers: ModifiersState::empty(),
+            }),
+            InputEvent::Resize { .. } => None,
+        }
+    }
+}
+
+/// Writes input events to a file, one JSON object per line, so that logs are easy to trim or
+/// edit by hand.
+pub struct InputRecorder {
+    writer: Option<BufWriter<File>>,
+}
+
+impl InputRecorder {
+    pub fn create(path: &Path) -> io::Result<InputRecorder> {
+        Ok(InputRecorder {
+            writer: Some(BufWriter::new(File::create(path)?)),
+        })
+    }
+
+    pub fn record(&mut self, frame: u64, event: InputEvent) {
+        let Some(writer) = &mut self.writer else {
+            return;
+        };
+
+        let result = serde_json::to_writer(&mut *writer, &Record { frame, event })
+            .map_err(io::Error::from)
+            .and_then(|()| writeln!(writer))
+            // flush every event, so the log survives the crash it's meant to reproduce.
+            .and_then(|()| writer.flush());
+        if let Err(e) = result {
+            warn!("Failed to record input, no longer recording: {}", e);
+            self.writer = None;
+        }
+    }
+}
+
+/// Feeds the events of an input log back to the application at the frames they were recorded.
+pub struct InputReplay {
+    records: VecDeque<Record>,
+}
+
+impl InputReplay {
+    pub fn load(path: &Path) -> io::Result<InputReplay> {
+        let mut records = VecDeque::new();
+        for (i, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: Record = serde_json::from_str(&line).map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", i + 1, e))
+            })?;
+            records.push_back(record);
+        }
+
+        Ok(InputReplay { records })
+    }
+
+    /// The next event recorded at or before `frame`, if any.
+    pub fn next_due(&mut self, frame: u64) -> Option<InputEvent> {
+        match self.records.front() {
+            Some(record) if record.frame <= frame => self.records.pop_front().map(|r| r.event),
+            _ => None,
+        }
+    }
+}
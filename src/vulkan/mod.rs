@@ -1,11 +1,32 @@
+pub mod allocator;
 pub mod application;
+pub mod capture;
+pub mod device_info;
+pub mod frame_pacer;
+pub mod gpu_profiler;
+pub mod input;
+pub mod input_log;
+pub mod uniform_buffer;
 pub mod window;
 
 pub struct WindowConfig {
     pub title: String,
-    pub width: u32,
-    pub height: u32,
+    /// `None` uses the primary monitor's resolution in fullscreen, or `DEFAULT_WIDTH` x
+    /// `DEFAULT_HEIGHT` in a window.
+    pub width: Option<u32>,
+    pub height: Option<u32>,
     pub cursor_disabled: bool,
     pub fullscreen: bool,
+    /// A borderless window covering the monitor at the desktop resolution, instead of an
+    /// exclusive fullscreen video mode. `width` and `height` are ignored.
+    pub borderless: bool,
+    /// The index of the monitor to open the window or go fullscreen on, as listed by
+    /// `--list-monitors`. `None`, or an index out of range, uses the primary monitor.
+    pub monitor: Option<usize>,
     pub resizable: bool,
 }
+
+impl WindowConfig {
+    pub const DEFAULT_WIDTH: u32 = 1280;
+    pub const DEFAULT_HEIGHT: u32 = 720;
+}
@@ -0,0 +1,21 @@
+use vulkano::buffer::BufferContents;
+
+/// Per-frame data read by the shaders, laid out to match the `UniformBufferObject` block in the
+/// original shaders.
+#[derive(BufferContents, Clone, Copy, Default)]
+#[repr(C)]
+pub struct UniformBufferObject {
+    pub model_view: [[f32; 4]; 4],
+    pub projection: [[f32; 4]; 4],
+    pub model_view_inverse: [[f32; 4]; 4],
+    pub projection_inverse: [[f32; 4]; 4],
+    pub aperture: f32,
+    pub focus_distance: f32,
+    pub heatmap_scale: f32,
+    pub total_number_of_samples: u32,
+    pub number_of_samples: u32,
+    pub number_of_bounces: u32,
+    pub random_seed: u32,
+    pub has_sky: u32,      // bool
+    pub show_heatmap: u32, // bool
+}
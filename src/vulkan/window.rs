@@ -7,3 +7,9 @@ pub struct Window {
     pub config: WindowConfig,
     pub window: Arc<WinitWindow>,
 }
+
+impl Window {
+    pub fn set_title(&self, title: &str) {
+        self.window.set_title(title);
+    }
+}